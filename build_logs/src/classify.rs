@@ -0,0 +1,114 @@
+//! The rules telling what went wrong from the tail of a build log
+
+use zhf_core::FailureCategory;
+
+/// Lines longer than this are cut off
+const MAX_LINE_LEN: usize = 300;
+
+/// Lines containing one of the patterns, compared in lowercase, belong to the category
+struct Rule {
+    category: FailureCategory,
+    patterns: &'static [&'static str],
+}
+
+/// The rules in the order they're tried. More specific causes come first, since their logs often
+/// end with generic errors too.
+const RULES: &[Rule] = &[
+    Rule {
+        category: FailureCategory::HashMismatch,
+        patterns: &["hash mismatch in fixed-output derivation", "hash mismatch"],
+    },
+    Rule {
+        category: FailureCategory::Timeout,
+        patterns: &["timed out after", "build timed out", "timeout reached"],
+    },
+    Rule {
+        category: FailureCategory::OutOfMemory,
+        patterns: &[
+            "out of memory",
+            "cannot allocate memory",
+            "std::bad_alloc",
+            "memoryerror",
+            "killed signal terminated program",
+            "oom-kill",
+        ],
+    },
+    Rule {
+        category: FailureCategory::Sandbox,
+        patterns: &[
+            "could not resolve host",
+            "temporary failure in name resolution",
+            "name or service not known",
+            "network is unreachable",
+            "/homeless-shelter",
+            "read-only file system",
+        ],
+    },
+    Rule {
+        category: FailureCategory::MissingDependency,
+        patterns: &[
+            "modulenotfounderror",
+            "no module named",
+            "command not found",
+            "not found in the pkg-config search path",
+            "no package '",
+            "could not find a package configuration file",
+            "cannot find -l",
+            ".h: no such file or directory",
+        ],
+    },
+    Rule {
+        category: FailureCategory::TestFailure,
+        patterns: &[
+            "assertionerror",
+            "test failed",
+            "tests failed",
+            "failed tests",
+            "test result: failed",
+            "fail:",
+            "--- fail",
+        ],
+    },
+    Rule {
+        category: FailureCategory::CompilerError,
+        patterns: &[
+            "error[e",
+            ": error:",
+            "undefined reference to",
+            "syntaxerror",
+            "cannot find symbol",
+        ],
+    },
+];
+
+/// Classifies the tail of a build log, returning the category together with the line that gave
+/// it away. Within a rule, the last matching line wins. If no rule matches, the last line is
+/// returned.
+pub fn classify(log: &str) -> (FailureCategory, String) {
+    let lines: Vec<&str> = log
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    for rule in RULES {
+        let matching = lines.iter().rev().find(|line| {
+            let line = line.to_lowercase();
+            rule.patterns.iter().any(|pattern| line.contains(pattern))
+        });
+        if let Some(line) = matching {
+            return (rule.category, shorten(line));
+        }
+    }
+    (
+        FailureCategory::Unknown,
+        lines.last().map(|line| shorten(line)).unwrap_or_default(),
+    )
+}
+
+/// Cuts a line off at `MAX_LINE_LEN` characters
+fn shorten(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_LEN) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line.to_string(),
+    }
+}
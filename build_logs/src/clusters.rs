@@ -0,0 +1,174 @@
+//! Groups the classified failures of many packages by what went wrong, so mass breakages like a
+//! compiler update show up as one large cluster instead of hundreds of rows
+
+use anyhow::Result;
+use regex::{Captures, Regex};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::OnceLock;
+use zhf_core::{dedup_builds, escape_html, Build, FailureCategory, Store, System};
+
+/// How many clusters the page lists
+const MAX_CLUSTERS: usize = 100;
+
+/// How many attributes of a cluster the page links to
+const MAX_EXAMPLES: usize = 5;
+
+/// Failed builds whose logs have the same signature
+pub struct FailureCluster {
+    pub category: FailureCategory,
+    pub signature: String,
+    /// The builds of the cluster, sorted by evaluation and attribute
+    pub builds: Vec<Build>,
+}
+
+impl FailureCluster {
+    /// The systems the builds of the cluster failed on
+    pub fn systems(&self) -> BTreeSet<&System> {
+        self.builds.iter().map(|build| &build.system).collect()
+    }
+}
+
+/// The patterns replaced when normalizing a log line
+struct Patterns {
+    sri_hash: Regex,
+    store_path: Regex,
+    /// Words of hex digits or Nix base32, which are hashes if they contain a digit
+    hash: Regex,
+    /// Locations in source files like `src/foo.c:12:5`
+    location: Regex,
+    path: Regex,
+    version: Regex,
+    number: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        sri_hash: Regex::new(r"\bsha(?:1|256|512)[-:][A-Za-z0-9+/=]+").unwrap(),
+        store_path: Regex::new(r#"/nix/store/[0-9a-z]{32}-[^\s'"`:;,()\[\]]*"#).unwrap(),
+        hash: Regex::new(r"\b(?:[0-9a-f]{7,}|[0-9a-z]{32,})\b").unwrap(),
+        location: Regex::new(r"[\w./+@~-]+:\d+(?::\d+)?").unwrap(),
+        path: Regex::new(r"\.{0,2}(?:/[\w.+@~-]+)+/?|(?:[\w.+@~-]+/)+[\w.+@~-]*").unwrap(),
+        version: Regex::new(r"\bv?\d+(?:\.\d+)+[\w.+~-]*").unwrap(),
+        number: Regex::new(r"\d+").unwrap(),
+    })
+}
+
+/// Normalizes the key line of a log, so the same error in different packages has the same
+/// signature. Paths, hashes, version numbers and other numbers are replaced by placeholders.
+pub fn signature(line: &str) -> String {
+    let patterns = patterns();
+    let line = patterns.sri_hash.replace_all(line, "<hash>");
+    let line = patterns.store_path.replace_all(&line, "<path>");
+    let line = patterns.hash.replace_all(&line, |captures: &Captures| {
+        let word = &captures[0];
+        if word.bytes().any(|b| b.is_ascii_digit()) {
+            "<hash>".to_string()
+        } else {
+            word.to_string()
+        }
+    });
+    let line = patterns.location.replace_all(&line, "<location>");
+    let line = patterns.path.replace_all(&line, "<path>");
+    let line = patterns.version.replace_all(&line, "<version>");
+    let line = patterns.number.replace_all(&line, "N");
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Groups the classified failures of the given evaluations by category and signature, counting
+/// each derivation once, largest clusters first
+pub fn failure_clusters(store: &Store, eval_ids: &[u64]) -> Result<Vec<FailureCluster>> {
+    let mut evals = vec![];
+    let mut logs = HashMap::new();
+    for eval_id in eval_ids {
+        evals.extend(store.eval(*eval_id)?);
+        logs.extend(store.classified_logs(*eval_id)?);
+    }
+    // Derivations referenced by several jobsets are counted once
+    let mut clusters: BTreeMap<(FailureCategory, String), Vec<Build>> = BTreeMap::new();
+    for unique in dedup_builds(&evals) {
+        if let Some(log) = logs.get(&unique.build.id) {
+            clusters
+                .entry((log.category, signature(&log.line)))
+                .or_default()
+                .push(unique.build);
+        }
+    }
+    let mut clusters: Vec<FailureCluster> = clusters
+        .into_iter()
+        .map(|((category, signature), builds)| FailureCluster {
+            category,
+            signature,
+            builds,
+        })
+        .collect();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.builds.len()));
+    Ok(clusters)
+}
+
+/// Renders the page with the largest failure clusters to `out`
+pub fn render_clusters_page(
+    hydra_url: &str,
+    site_url: &str,
+    clusters: &[FailureCluster],
+    out: &Path,
+) -> Result<()> {
+    let mut out = File::create(out)?;
+    out.write_fmt(format_args!(r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <meta http-equiv="X-UA-Compatible" content="ie=edge">
+        <title>Top failure clusters</title>
+        <link rel="stylesheet" href="../style.css">
+        <link rel="icon" type="image/x-icon" href="../favicon.ico">
+        <meta property="og:title" content="Top failure clusters" />
+        <meta property="og:description" content="Hydra failures grouped by the error in their logs" />
+        <meta property="og:type" content="website" />
+        <meta property="og:url" content="{site_url}/failed/clusters.html" />
+        <meta property="og:image" content="../icon.png" />
+      </head>
+      <body>
+        <h1><a href="../index.html" title="Go Home"><img src="../nix-snowflake.svg"></a>Top failure clusters</h1>
+        <p>Builds that failed by themselves, grouped by the error in their logs. Paths, hashes and version numbers are left out of the errors.</p>
+        <table>
+          <thead><tr><th>Category</th><th>Error</th><th>Builds</th><th>Platforms</th><th>Examples</th></tr></thead>
+          <tbody>"#))?;
+    for cluster in clusters.iter().take(MAX_CLUSTERS) {
+        let systems: Vec<&str> = cluster.systems().into_iter().map(System::as_str).collect();
+        let mut examples: Vec<String> = cluster
+            .builds
+            .iter()
+            .take(MAX_EXAMPLES)
+            .map(|build| {
+                format!(
+                    "<a href=\"{hydra_url}/build/{}\">{}</a>",
+                    build.id,
+                    escape_html(&build.attr)
+                )
+            })
+            .collect();
+        if cluster.builds.len() > MAX_EXAMPLES {
+            examples.push(format!("and {} more", cluster.builds.len() - MAX_EXAMPLES));
+        }
+        out.write_fmt(format_args!(
+            "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            cluster.category.title(),
+            escape_html(&cluster.signature),
+            cluster.builds.len(),
+            systems.join(", "),
+            examples.join("<br>"),
+        ))?;
+    }
+    if clusters.is_empty() {
+        out.write_fmt(format_args!(
+            r#"<tr><td colspan="5" class="none">None 🎉</td></tr>"#
+        ))?;
+    }
+    out.write_fmt(format_args!("</tbody></table></body></html>"))?;
+    Ok(())
+}
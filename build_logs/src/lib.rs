@@ -0,0 +1,157 @@
+//! Classify what went wrong in builds that failed by themselves
+//!
+//! The tail of the raw log of the failed step of each such build is downloaded and matched against
+//! a set of rules, see [`classify`]. Builds that timed out are classified without their log. Builds
+//! whose logs have the same [`signature`] are grouped into clusters.
+
+mod classify;
+mod clusters;
+
+pub use classify::classify;
+pub use clusters::{failure_clusters, render_clusters_page, signature, FailureCluster};
+
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, StreamExt};
+use reqwest_middleware::ClientWithMiddleware;
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write};
+use std::path::Path;
+use zhf_core::{parse_lines, Build, BuildStatus, ClassifiedLog, Eval, FailureCategory, Store};
+
+/// How many logs are fetched at the same time
+const CONCURRENCY: usize = 16;
+
+/// How many lines at the end of a log are fetched and classified
+const TAIL_LINES: usize = 200;
+
+/// Classifies the logs of the builds of the given evaluations that failed by themselves, writes
+/// them to `data_dir/logcache/{eval}.cache` and records them in the store.
+///
+/// The cache of an evaluation is only written if all of its logs were fetched, so the missing
+/// ones are retried by the next run. Caches of evaluations that are not given are purged.
+pub async fn classify_failed_builds(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    data_dir: &Path,
+    store: &Store,
+    argv: &[u64],
+) -> Result<()> {
+    log::info!("Will classify the logs of evaluations: {:?}", argv);
+    let log_cache = data_dir.join("logcache");
+    create_dir_all(&log_cache)?;
+
+    for eval_id in argv {
+        let cache_loc = log_cache.join(format!("{eval_id}.cache"));
+        if cache_loc.exists() {
+            log::info!("Skipping {eval_id} because it's already cached");
+            if store.classified_logs(*eval_id)?.is_empty() {
+                let logs = parse_lines(&read_to_string(&cache_loc)?)
+                    .with_context(|| format!("Invalid cache {}", cache_loc.display()))?;
+                store.record_classified_logs(&logs)?;
+            }
+            continue;
+        }
+
+        let eval = Eval::read_cache(
+            *eval_id,
+            &data_dir.join("evalcache").join(format!("{eval_id}.cache")),
+        )?;
+        let builds: Vec<Build> = eval
+            .builds
+            .into_iter()
+            .filter(|build| build.status.is_own_failure())
+            .collect();
+        log::info!("Classifying {} direct failures of {eval_id}", builds.len());
+        let results: Vec<(u64, Result<Option<ClassifiedLog>>)> = stream::iter(&builds)
+            .map(|build| async move {
+                (
+                    build.id,
+                    classify_build(http_client, hydra_url, build).await,
+                )
+            })
+            .buffer_unordered(CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut logs = vec![];
+        let mut failed = 0;
+        for (build_id, result) in results {
+            match result {
+                Ok(Some(log)) => logs.push(log),
+                Ok(None) => log::debug!("Build #{build_id} has no log of a failed step"),
+                Err(e) => {
+                    log::error!("Failed classifying the log of build #{build_id}: {e:#}");
+                    failed += 1;
+                }
+            }
+        }
+        logs.sort_by_key(|log| log.build_id);
+        store.record_classified_logs(&logs)?;
+        if failed > 0 {
+            log::error!(
+                "Not caching evaluation {eval_id} because {failed} logs failed, they are retried in the next run"
+            );
+            continue;
+        }
+        let new_loc = log_cache.join(format!("{eval_id}.cache.new"));
+        let contents: String = logs.iter().map(|log| format!("{log}\n")).collect();
+        write(&new_loc, contents)?;
+        rename(&new_loc, &cache_loc)?;
+    }
+
+    // Clean cache
+    for entry in read_dir(&log_cache)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| anyhow!("Cache entry has no filename"))?;
+        let Some(id) = [".cache", ".cache.new"]
+            .iter()
+            .find_map(|suffix| file_name.strip_suffix(suffix))
+            .and_then(|id| id.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        if !argv.contains(&id) {
+            log::info!("Purging {file_name}");
+            remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetches and classifies the log of a build that failed by itself. Returns `None` if the build
+/// has no failed step with a log, e.g. because it's a cached failure.
+async fn classify_build(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    build: &Build,
+) -> Result<Option<ClassifiedLog>> {
+    if build.status == BuildStatus::TimedOut {
+        return Ok(Some(ClassifiedLog {
+            build_id: build.id,
+            category: FailureCategory::Timeout,
+            line: String::new(),
+        }));
+    }
+    let steps = most_important_deps::fetch_build_steps(hydra_url, build.id, http_client).await?;
+    let Some(log) = steps.failed.into_iter().find_map(|step| step.log) else {
+        return Ok(None);
+    };
+    // The log page itself only loads the log with JavaScript, and full logs can be huge
+    let tail = http_client
+        .get(format!("{hydra_url}{log}/raw?tail={TAIL_LINES}"))
+        // Logs of finished builds don't change
+        .with_extension(hydra_client::Immutable)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let (category, line) = classify(&tail);
+    Ok(Some(ClassifiedLog {
+        build_id: build.id,
+        category,
+        line,
+    }))
+}
@@ -0,0 +1,43 @@
+//! Classify the logs of the builds of evaluations that failed by themselves and render
+//! `public/failed/clusters.html`
+//!
+//! Usage: `build_logs [--offline] eval_id...`
+
+use anyhow::{Context, Result};
+
+#[tokio::main(worker_threads = 4)]
+async fn main() -> Result<()> {
+    env_logger::builder().format_timestamp(None).init();
+    // Handle args
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let offline = hydra_client::take_offline_flag(&mut args);
+    let argv = args
+        .iter()
+        .map(|x| {
+            x.parse::<u64>()
+                .with_context(|| format!("Invalid evaluation {x:?}"))
+        })
+        .collect::<Result<Vec<u64>>>()?;
+
+    let config = zhf_core::Config::load()?;
+    let branch = config.branch(None)?.clone();
+    let data_dir = std::env::current_dir()?.join("data");
+    let failed_dir = std::env::current_dir()?.join("public").join("failed");
+
+    let (http_client, metrics) = hydra_client::client(&config.http, &data_dir, offline)?;
+
+    let store = zhf_core::Store::open_in(&data_dir)?;
+    build_logs::classify_failed_builds(&http_client, &branch.hydra_url(), &data_dir, &store, &argv)
+        .await?;
+    log::info!("Hydra requests: {metrics}");
+
+    let clusters = build_logs::failure_clusters(&store, &argv)?;
+    std::fs::create_dir_all(&failed_dir)?;
+    build_logs::render_clusters_page(
+        &branch.hydra_url(),
+        branch.site_url(),
+        &clusters,
+        &failed_dir.join("clusters.html"),
+    )?;
+    Ok(())
+}
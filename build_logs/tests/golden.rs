@@ -0,0 +1,128 @@
+//! Run `build_logs` against recorded Hydra pages
+
+use hydra_fixtures::{pages_dir, FixtureServer};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use zhf_core::{FailureCategory, Store};
+
+/// The golden caches of the tests
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Runs `build_logs` on evals 1001 and 2000 in `work_dir`, returning the data directory
+fn run(work_dir: &Path) -> PathBuf {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let golden = golden_dir();
+    let data_dir = work_dir.join("data");
+    std::fs::create_dir_all(data_dir.join("evalcache")).unwrap();
+    let store = Store::open_in(&data_dir).unwrap();
+    for eval in [1001, 2000] {
+        let cache = data_dir.join("evalcache").join(format!("{eval}.cache"));
+        std::fs::copy(
+            golden.join("evalcache").join(format!("{eval}.cache")),
+            &cache,
+        )
+        .unwrap();
+        store
+            .record_builds(&zhf_core::Eval::read_cache(eval, &cache).unwrap())
+            .unwrap();
+    }
+
+    let status = Command::new(env!("CARGO_BIN_EXE_build_logs"))
+        .args(["1001", "2000"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    data_dir
+}
+
+#[test]
+fn classifies_failed_builds() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let data_dir = run(work_dir.path());
+    for eval in ["1001", "2000"] {
+        let file = format!("{eval}.cache");
+        assert_eq!(
+            std::fs::read_to_string(data_dir.join("logcache").join(&file)).unwrap(),
+            std::fs::read_to_string(golden_dir().join("logcache").join(&file)).unwrap(),
+        );
+    }
+
+    let store = Store::open_in(&data_dir).unwrap();
+    let logs = store.classified_logs(1001).unwrap();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[&102].category, FailureCategory::CompilerError);
+    assert_eq!(logs[&104].category, FailureCategory::Timeout);
+    assert_eq!(
+        store.classified_logs(2000).unwrap()[&202].category,
+        FailureCategory::TestFailure
+    );
+
+    let clusters =
+        std::fs::read_to_string(work_dir.path().join("public/failed/clusters.html")).unwrap();
+    assert!(clusters.contains(
+        "<tr><td>Compiler error</td><td><code>&lt;location&gt;: error: implicit declaration of function &#39;bar&#39; [-Werror=implicit-function-declaration]</code></td>\
+         <td>1</td><td>x86_64-linux</td><td><a href=\""
+    ));
+}
+
+#[test]
+fn classifies_common_failures() {
+    for (log, category) in [
+        (
+            "error: hash mismatch in fixed-output derivation '/nix/store/…-src.drv':\n  specified: sha256-AAAA\n     got:    sha256-BBBB",
+            FailureCategory::HashMismatch,
+        ),
+        (
+            "ModuleNotFoundError: No module named 'numpy'",
+            FailureCategory::MissingDependency,
+        ),
+        (
+            "error[E0308]: mismatched types\nerror: could not compile `foo`",
+            FailureCategory::CompilerError,
+        ),
+        (
+            "c++: fatal error: Killed signal terminated program cc1plus",
+            FailureCategory::OutOfMemory,
+        ),
+        (
+            "curl: (6) Could not resolve host: example.org",
+            FailureCategory::Sandbox,
+        ),
+        ("make: *** [all] Error 2", FailureCategory::Unknown),
+    ] {
+        assert_eq!(build_logs::classify(log).0, category, "{log}");
+    }
+    assert_eq!(
+        build_logs::classify("error[E0308]: mismatched types\nerror: could not compile `foo`").1,
+        "error[E0308]: mismatched types"
+    );
+}
+
+#[test]
+fn clusters_failures_by_signature() {
+    let signature = |line| build_logs::signature(line);
+    assert_eq!(
+        signature("src/parser.c:1234:17: error: 'gets' undeclared"),
+        signature("/build/libfoo-2.3.1/lib/io.c:7:1: error: 'gets' undeclared"),
+    );
+    assert_eq!(
+        signature(
+            "ERROR: Could not find a version that satisfies the requirement setuptools>=68.0.0"
+        ),
+        "ERROR: Could not find a version that satisfies the requirement setuptools>=<version>",
+    );
+    assert_eq!(
+        signature("cannot open /nix/store/0c4sxzvmnmn4ljpbm8x0g1kkw5vmkxi1-python3-3.11.4/lib/libpython3.11.so: No such file"),
+        "cannot open <path>: No such file",
+    );
+    assert_eq!(
+        signature("error: builder for '/nix/store/x.drv' failed: hash 3f2a9c1 sha256-Zm9vYmFy= 42"),
+        "error: builder for '<path>' failed: hash <hash> <hash> N",
+    );
+}
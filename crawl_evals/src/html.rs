@@ -0,0 +1,96 @@
+//! Scrapes the builds of an evaluation from the full HTML page of the evaluation
+
+use crate::EvalFetcher;
+use anyhow::Result;
+use reqwest_middleware::ClientWithMiddleware;
+use select::node::Node;
+use select::predicate::Name;
+use std::collections::{BTreeMap, HashMap};
+use zhf_core::{Build, System};
+
+pub struct HtmlFetcher {
+    pub http_client: ClientWithMiddleware,
+    pub hydra_url: String,
+}
+
+impl EvalFetcher for HtmlFetcher {
+    async fn fetch_builds(&self, eval_id: u64) -> Result<Vec<Build>> {
+        // Holds all builds by attr name to dedup them
+        let mut builds = HashMap::new();
+
+        let res = self
+            .http_client
+            .get(format!("{}/eval/{eval_id}?full=1", self.hydra_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        // Parse output
+        let doc = select::document::Document::from(&res[..]);
+
+        for table in doc.find(Name("tbody")) {
+            for row in table.find(Name("tr")) {
+                let cols: Vec<Node> = row.find(Name("td")).collect();
+                // Skip input changes
+                if cols.is_empty() {
+                    continue;
+                }
+                // Skip removed jobs
+                if cols.len() == 2 {
+                    continue;
+                }
+                // Skip inputs
+                if cols.len() == 5 {
+                    continue;
+                }
+                // Skip invalid rows
+                if cols.len() != 6 {
+                    log::warn!("Skipping invalid row with {} columns: {:?}", cols.len(), row);
+                    continue;
+                }
+                if cols[0].find(Name("img")).next().is_none() {
+                    continue;
+                }
+                // Name
+                let attr_name = if let Some(attr_name) = cols[2].find(Name("a")).next() { attr_name.text() } else {
+                    log::warn!("Job has no attr name: {:?}", row);
+                    continue;
+                };
+                // Status
+                let status = if let Some(status) = cols[0].find(Name("img")).next() { status } else {
+                    log::warn!("Job has no status: {:?}", row);
+                    continue;
+                };
+                let status = if let Some(Ok(status)) = status.attr("title").map(str::parse) { status } else {
+                    log::warn!("Job has no status: {:?}", row);
+                    continue;
+                };
+                // Build ID
+                let build_id = if let Some(Ok(build_id)) = cols[1].find(Name("a")).next().map(|x| x.text().parse()) { build_id } else {
+                    log::warn!("Job has no build ID: {:?}", row);
+                    continue;
+                };
+                // Package name
+                let pkg_name = cols[4].text();
+                // Architecture
+                let arch: System = if let Some(Ok(arch)) = cols[5].find(Name("tt")).next().map(|x| x.text().parse()) { arch } else {
+                    log::warn!("Job has no architecture: {:?}", row);
+                    continue;
+                };
+
+                builds.insert(attr_name.clone(), Build {
+                    attr: attr_name,
+                    id: build_id,
+                    name: pkg_name,
+                    system: arch,
+                    status,
+                    drv_path: None,
+                    outputs: BTreeMap::new(),
+                });
+            }
+        }
+
+        Ok(builds.into_values().collect())
+    }
+}
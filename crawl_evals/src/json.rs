@@ -0,0 +1,130 @@
+//! Fetches the builds of an evaluation from the JSON API of Hydra
+
+use crate::EvalFetcher;
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use zhf_core::{Build, BuildStatus};
+
+/// How many builds are looked up at the same time
+const BATCH_SIZE: usize = 16;
+
+pub struct JsonFetcher {
+    pub http_client: ClientWithMiddleware,
+    pub hydra_url: String,
+}
+
+#[derive(Deserialize)]
+struct HydraEval {
+    builds: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct HydraBuild {
+    id: u64,
+    job: String,
+    nixname: String,
+    system: String,
+    finished: u8,
+    buildstatus: Option<u64>,
+    drvpath: Option<String>,
+    #[serde(default)]
+    buildoutputs: BTreeMap<String, HydraOutput>,
+}
+
+#[derive(Deserialize)]
+struct HydraOutput {
+    path: String,
+}
+
+impl HydraBuild {
+    /// The status as it's shown by the status icon in the web interface
+    fn status(&self) -> BuildStatus {
+        if self.finished == 0 {
+            return BuildStatus::Scheduled;
+        }
+        match self.buildstatus {
+            Some(0) => BuildStatus::Succeeded,
+            Some(1) | None => BuildStatus::Failed,
+            Some(2) => BuildStatus::DependencyFailed,
+            Some(3) => BuildStatus::Aborted,
+            Some(4) => BuildStatus::Cancelled,
+            Some(6) => BuildStatus::FailedWithOutput,
+            Some(7) => BuildStatus::TimedOut,
+            Some(8) => BuildStatus::CachedFailure,
+            Some(9) => BuildStatus::UnsupportedSystem,
+            Some(10) => BuildStatus::LogLimitExceeded,
+            Some(11) => BuildStatus::OutputLimitExceeded,
+            Some(12) => BuildStatus::NonDeterministic,
+            Some(other) => BuildStatus::Unknown(format!("Build status {other}")),
+        }
+    }
+}
+
+impl JsonFetcher {
+    /// Fetches JSON, answers of `immutable` requests are served from the cache without asking Hydra
+    async fn get<T: for<'de> Deserialize<'de>>(&self, url: String, immutable: bool) -> Result<T> {
+        let mut request = self
+            .http_client
+            .get(&url)
+            .header("Accept", "application/json");
+        if immutable {
+            request = request.with_extension(hydra_client::Immutable);
+        }
+        let res = request.send().await?.error_for_status()?.text().await?;
+        serde_json::from_str(&res).with_context(|| format!("Invalid JSON from {url}"))
+    }
+
+    /// Fetches a build, which is known to be `finished` if it's only fetched again from the cache
+    pub async fn fetch_build(&self, build_id: u64, finished: bool) -> Result<Build> {
+        let build: HydraBuild = self
+            .get(format!("{}/build/{build_id}", self.hydra_url), finished)
+            .await?;
+        Ok(Build {
+            attr: build.job.clone(),
+            id: build.id,
+            name: build.nixname.clone(),
+            system: build.system.parse()?,
+            status: build.status(),
+            drv_path: build.drvpath.clone(),
+            outputs: build
+                .buildoutputs
+                .into_iter()
+                .map(|(name, output)| (name, output.path))
+                .collect(),
+        })
+    }
+}
+
+impl EvalFetcher for JsonFetcher {
+    async fn fetch_builds(&self, eval_id: u64) -> Result<Vec<Build>> {
+        let eval: HydraEval = self
+            .get(format!("{}/eval/{eval_id}", self.hydra_url), false)
+            .await?;
+        log::info!("Evaluation {eval_id} has {} builds", eval.builds.len());
+
+        let builds: Vec<Build> = stream::iter(eval.builds)
+            .map(|build_id| async move {
+                self.fetch_build(build_id, false)
+                    .await
+                    .with_context(|| format!("Failed fetching build #{build_id}"))
+            })
+            .buffer_unordered(BATCH_SIZE)
+            .try_collect()
+            .await?;
+
+        // Dedup by attr name, preferring the newest build of a job
+        let mut by_attr: HashMap<String, Build> = HashMap::new();
+        for build in builds {
+            match by_attr.get(&build.attr) {
+                Some(existing) if existing.id > build.id => {}
+                _ => {
+                    by_attr.insert(build.attr.clone(), build);
+                }
+            }
+        }
+        Ok(by_attr.into_values().collect())
+    }
+}
@@ -0,0 +1,224 @@
+//! Crawl the full table of all builds from a evaluation
+
+mod html;
+mod json;
+
+use anyhow::{anyhow, Result};
+use futures::stream::{self, StreamExt};
+use reqwest_middleware::ClientWithMiddleware;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::str::FromStr;
+use zhf_core::{Build, BuildStatus, Eval, FailureBreakdown, Jobset, Store};
+
+/// A way of finding all builds of an evaluation
+trait EvalFetcher {
+    /// Fetches all builds of the evaluation, deduplicated by attribute
+    async fn fetch_builds(&self, eval_id: u64) -> Result<Vec<Build>>;
+}
+
+/// Which `EvalFetcher` to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Scrape the full evaluation page
+    Html,
+    /// Use the JSON API of the evaluation and all of its builds
+    Json,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "html" => Ok(Backend::Html),
+            "json" => Ok(Backend::Json),
+            other => Err(anyhow!("Unknown backend {other:?}")),
+        }
+    }
+}
+
+/// Warns about the builds of an evaluation whose status isn't recognized, so it can be added to
+/// `BuildStatus`
+fn report_unknown_statuses(eval: &Eval) {
+    let mut unknown: BTreeMap<&BuildStatus, Vec<u64>> = BTreeMap::new();
+    for build in eval.builds.iter().filter(|build| !build.status.is_known()) {
+        unknown.entry(&build.status).or_default().push(build.id);
+    }
+    for (status, builds) in unknown {
+        log::warn!(
+            "Eval {}: {} builds have the unrecognized status {:?}, e.g. build {}",
+            eval.id,
+            builds.len(),
+            status.to_string(),
+            builds[0]
+        );
+    }
+}
+
+/// How many builds are looked up at the same time
+const LOOKUP_BATCH_SIZE: usize = 16;
+
+/// Looks up the derivation and output paths of the builds that don't know them yet, so identical
+/// derivations can be told apart across jobsets. The paths of builds of the previous evaluation of
+/// the jobset whose status flipped between succeeding and failing are looked up too, so flaky
+/// builds can be told apart from broken and fixed ones, and recorded in the store. Paths recorded
+/// for the same build ID are reused, only the remaining builds are fetched from their JSON. Builds
+/// whose paths can't be fetched are left without them.
+async fn fetch_store_paths(
+    json_fetcher: &json::JsonFetcher,
+    store: &Store,
+    eval_id: u64,
+    builds: &mut [Build],
+) -> Result<()> {
+    let previous = match store.stored_eval(eval_id)? {
+        Some(eval) => store.previous_eval(&eval)?,
+        None => None,
+    };
+    let mut previous = match previous {
+        Some(previous) => store.eval(previous.id)?.map(|eval| eval.builds),
+        None => None,
+    }
+    .unwrap_or_default();
+    store.fill_store_paths(builds)?;
+    store.fill_store_paths(&mut previous)?;
+    let previous: HashMap<&str, &Build> = previous
+        .iter()
+        .map(|build| (build.attr.as_str(), build))
+        .collect();
+
+    // Whether each build is finished, so its answer never changes
+    let mut missing: BTreeMap<u64, bool> = builds
+        .iter()
+        .filter(|build| build.drv_path.is_none())
+        .map(|build| (build.id, build.status.is_finished()))
+        .collect();
+    let mut flipped = vec![];
+    for build in builds.iter() {
+        let Some(old) = previous.get(build.attr.as_str()) else {
+            continue;
+        };
+        // Hydra reuses the build of an unchanged derivation
+        if old.id == build.id || !old.status.flips_to(&build.status) {
+            continue;
+        }
+        if old.drv_path.is_none() {
+            missing.insert(old.id, true);
+        }
+        flipped.push(*old);
+    }
+    if !missing.is_empty() {
+        log::info!("Looking up the store paths of {} builds", missing.len());
+    }
+    let found: HashMap<u64, Build> = stream::iter(missing)
+        .map(|(build_id, finished)| async move {
+            (build_id, json_fetcher.fetch_build(build_id, finished).await)
+        })
+        .buffer_unordered(LOOKUP_BATCH_SIZE)
+        .filter_map(|(build_id, build)| async move {
+            match build {
+                Ok(build) => Some((build_id, build)),
+                Err(e) => {
+                    log::warn!("Failed looking up the store paths of build #{build_id}: {e:#}");
+                    None
+                }
+            }
+        })
+        .collect()
+        .await;
+    for build in builds.iter_mut() {
+        if let Some(found) = found.get(&build.id) {
+            build.drv_path = found.drv_path.clone();
+            build.outputs = found.outputs.clone();
+        }
+    }
+    // Paths reused from other builds are recorded too, the previous evaluation may not have them
+    let flipped: Vec<Build> = flipped
+        .into_iter()
+        .filter_map(|old| found.get(&old.id).or(old.drv_path.is_some().then_some(old)))
+        .cloned()
+        .collect();
+    store.record_store_paths(&flipped)?;
+    Ok(())
+}
+
+/// Counts the failed builds of an evaluation by system and status from its page, without looking
+/// up their store paths. Only builds for the systems configured for the jobset are counted.
+pub async fn fetch_failure_breakdown(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    eval_id: u64,
+    jobset: &Jobset,
+) -> Result<FailureBreakdown> {
+    let html_fetcher = html::HtmlFetcher {
+        http_client: http_client.clone(),
+        hydra_url: hydra_url.to_string(),
+    };
+    let builds = html_fetcher.fetch_builds(eval_id).await?;
+    Ok(FailureBreakdown::from_builds(
+        builds.iter().filter(|build| jobset.takes(&build.system)),
+    ))
+}
+
+/// Crawls all builds of the given evaluations into `data_dir/evalcache/{eval}.cache` and records
+/// them in the store.
+///
+/// Evaluations are given together with their jobset, only builds for the systems configured for
+/// the jobset are kept. Both backends record the derivation and output paths of every build, the
+/// HTML backend looks them up from the JSON of each build.
+pub async fn crawl_evals(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    data_dir: &Path,
+    store: &Store,
+    evals: &[(u64, &Jobset)],
+    backend: Backend,
+) -> Result<()> {
+    log::info!(
+        "Will crawl evaluations using the {backend:?} backend: {:?}",
+        evals.iter().map(|(e, _)| e).collect::<Vec<_>>()
+    );
+
+    // Prepare directories
+    let mut eval_cache_dir = data_dir.to_path_buf();
+    eval_cache_dir.push("evalcache");
+    create_dir_all(&eval_cache_dir)?;
+
+    let html_fetcher = html::HtmlFetcher {
+        http_client: http_client.clone(),
+        hydra_url: hydra_url.to_string(),
+    };
+    let json_fetcher = json::JsonFetcher {
+        http_client: http_client.clone(),
+        hydra_url: hydra_url.to_string(),
+    };
+
+    for &(eval_id, jobset) in evals {
+        let mut cache_file = eval_cache_dir.clone();
+        cache_file.push(format!("{eval_id}.cache"));
+        if cache_file.exists() {
+            log::info!("Evaluation {eval_id} is already cached");
+            if store.eval(eval_id)?.is_none() {
+                store.record_builds(&Eval::read_cache(eval_id, &cache_file)?)?;
+            }
+            continue;
+        }
+
+        let builds = match backend {
+            Backend::Html => html_fetcher.fetch_builds(eval_id).await?,
+            Backend::Json => json_fetcher.fetch_builds(eval_id).await?,
+        };
+        let mut builds: Vec<Build> = builds
+            .into_iter()
+            .filter(|build| jobset.takes(&build.system))
+            .collect();
+        fetch_store_paths(&json_fetcher, store, eval_id, &mut builds).await?;
+
+        let eval = Eval::new(eval_id, builds);
+        report_unknown_statuses(&eval);
+        eval.write_cache(&cache_file)?;
+        store.record_builds(&eval)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Run `crawl_evals` against recorded Hydra pages with both backends
+
+use hydra_fixtures::{assert_golden_dir, pages_dir, FixtureServer};
+use std::path::Path;
+use std::process::Command;
+use zhf_core::{Eval, Store, StoredEval};
+
+fn crawl_evals(extra_args: &[&str]) {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_crawl_evals"))
+        .args(extra_args)
+        .args(["1001", "linux", "2000", "darwin"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_golden_dir(
+        &work_dir.path().join("data"),
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden"),
+    );
+}
+
+#[test]
+fn html_backend() {
+    crawl_evals(&[]);
+}
+
+#[test]
+fn json_backend() {
+    crawl_evals(&["--backend", "json"]);
+}
+
+#[test]
+fn reports_unknown_statuses() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_crawl_evals"))
+        .args(["1000", "linux"])
+        .env("HYDRA_URL", server.url())
+        .env("RUST_LOG", "warn")
+        .current_dir(work_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "crawl_evals failed: {output:?}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr
+        .contains("Eval 1000: 1 builds have the unrecognized status \"Exploded\", e.g. build 92"));
+
+    // Known statuses are normalized, unknown ones kept verbatim
+    let cache = std::fs::read_to_string(work_dir.path().join("data/evalcache/1000.cache")).unwrap();
+    assert_eq!(
+        cache,
+        "nixpkgs.big.x86_64-linux 91 big-1.0 x86_64-linux Output size limit exceeded\n\
+         nixpkgs.boom.x86_64-linux 92 boom-2.0 x86_64-linux Exploded\n\
+         nixpkgs.foo.x86_64-linux 94 foo-1.0 x86_64-linux Succeeded \
+         /nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv \
+         out=/nix/store/rv2pl28z8nyq8q3bpdcpqqxjijfb4w4c-foo-1.0\n\
+         nixpkgs.hello.x86_64-linux 93 hello-2.12.1 x86_64-linux Succeeded\n"
+    );
+}
+
+#[test]
+fn looks_up_the_derivations_of_flipped_builds() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    for (id, time) in [
+        (1000, "2023-05-08 19:00:00 (UTC)"),
+        (1001, "2023-05-10 09:01:02 (UTC)"),
+    ] {
+        store
+            .record_eval(&StoredEval {
+                id,
+                branch: "master".to_string(),
+                jobset: "linux".to_string(),
+                failures: 0,
+                time: time.to_string(),
+            })
+            .unwrap();
+    }
+    // The previous evaluation was crawled before store paths were recorded
+    let previous = Eval::parse_cache(
+        1000,
+        "nixpkgs.foo.x86_64-linux 94 foo-1.0 x86_64-linux Succeeded\n\
+         nixpkgs.hello.x86_64-linux 93 hello-2.12.1 x86_64-linux Succeeded\n",
+    )
+    .unwrap();
+    store.record_builds(&previous).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_crawl_evals"))
+        .args(["1001", "linux"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Only foo flipped from succeeding to failing
+    let previous = store.eval(1000).unwrap().unwrap();
+    let foo = &previous.builds[0];
+    assert_eq!(
+        foo.drv_path.as_deref(),
+        Some("/nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv")
+    );
+    assert_eq!(
+        foo.outputs["out"],
+        "/nix/store/rv2pl28z8nyq8q3bpdcpqqxjijfb4w4c-foo-1.0"
+    );
+    assert_eq!(previous.builds[1].drv_path, None);
+}
+
+#[test]
+fn reuses_recorded_store_paths() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    // Hydra reused the build of hello, whose paths were recorded with an older evaluation
+    let older = Eval::parse_cache(
+        999,
+        "nixpkgs.hello.x86_64-linux 101 hello-2.12.1 x86_64-linux Succeeded \
+         /nix/store/00000000000000000000000000000000-hello-2.12.1.drv \
+         out=/nix/store/11111111111111111111111111111111-hello-2.12.1\n",
+    )
+    .unwrap();
+    store.record_builds(&older).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_crawl_evals"))
+        .args(["1001", "linux"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let eval = store.eval(1001).unwrap().unwrap();
+    let hello = eval.builds.iter().find(|build| build.id == 101).unwrap();
+    assert_eq!(hello, &older.builds[0]);
+    // The others were looked up
+    let foo = eval.builds.iter().find(|build| build.id == 102).unwrap();
+    assert_eq!(
+        foo.drv_path.as_deref(),
+        Some("/nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv")
+    );
+}
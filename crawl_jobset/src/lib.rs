@@ -0,0 +1,488 @@
+//! Crawl some data about the evaluations of a jobset from the Hydra web interface directly.
+//! We need to do this because the API doesn't offer this data.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest_middleware::ClientWithMiddleware;
+use select::node::Node;
+use select::predicate::{Class, Name, Predicate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// An evaluation as listed on the evaluations page of a jobset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobsetEval {
+    pub id: u64,
+    /// Number of failed builds
+    pub failures: u64,
+    /// Number of succeeded builds
+    pub successes: u64,
+    /// Number of builds that didn't finish yet
+    pub queued: u64,
+    /// When the evaluation happened, as shown by Hydra (`2023-05-10 09:01:02 (UTC)`)
+    pub time: String,
+    /// When the evaluation happened in RFC 3339 (`2023-05-10T09:01:02Z`)
+    pub datetime: String,
+    /// The abbreviated `nixpkgs` revision, if the evaluation changed it
+    pub nixpkgs_change: Option<String>,
+}
+
+impl JobsetEval {
+    /// Whether all builds of the evaluation finished
+    pub fn is_finished(&self) -> bool {
+        self.queued == 0
+    }
+}
+
+impl fmt::Display for JobsetEval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.id, self.failures, self.time)
+    }
+}
+
+/// How `crawl_jobset` prints the evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `{eval} {failures} {time}`
+    Text,
+    /// An [`EvalSummary`]
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            other => Err(anyhow!("Unknown format {other:?}")),
+        }
+    }
+}
+
+/// Everything callers need to know about an evaluation, printed by `crawl_jobset --format json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EvalSummary {
+    pub id: u64,
+    pub finished: u64,
+    pub queued: u64,
+    pub failed: u64,
+    pub succeeded: u64,
+    /// Whether all builds finished
+    pub all_finished: bool,
+    /// RFC 3339
+    pub time: String,
+    /// The revision of the `nixpkgs` input, if the evaluation has one
+    pub nixpkgs_revision: Option<String>,
+}
+
+/// The part of Hydra's JSON of an evaluation we care about
+#[derive(Deserialize)]
+struct HydraEval {
+    jobsetevalinputs: HashMap<String, HydraEvalInput>,
+}
+
+#[derive(Deserialize)]
+struct HydraEvalInput {
+    revision: Option<String>,
+}
+
+/// Describes an evaluation, asking Hydra for its `nixpkgs` revision
+pub async fn summarize(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    eval: &JobsetEval,
+) -> Result<EvalSummary> {
+    Ok(EvalSummary {
+        id: eval.id,
+        finished: eval.failures + eval.successes,
+        queued: eval.queued,
+        all_finished: eval.is_finished(),
+        failed: eval.failures,
+        succeeded: eval.successes,
+        time: eval.datetime.clone(),
+        nixpkgs_revision: nixpkgs_revision(http_client, hydra_url, eval.id).await?,
+    })
+}
+
+/// The number in a badge of an evaluation row, 0 if there is none
+fn badge(row: &Node, class: &str) -> Result<u64> {
+    match row.find(Class(class)).next() {
+        Some(badge) => badge
+            .text()
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid {class} count {:?}", badge.text())),
+        None => Ok(0),
+    }
+}
+
+/// Parses a row of the evaluations table
+fn parse_row(row: &Node) -> Result<JobsetEval> {
+    let time = row
+        .find(Name("time"))
+        .next()
+        .ok_or_else(|| anyhow!("No time found"))?;
+    let datetime = time
+        .attr("datetime")
+        .ok_or_else(|| anyhow!("No datetime found"))?;
+    let datetime = DateTime::parse_from_rfc3339(datetime)
+        .with_context(|| format!("Invalid datetime {datetime:?}"))?
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Secs, true);
+    Ok(JobsetEval {
+        id: row
+            .find(Name("a"))
+            .next()
+            .ok_or_else(|| anyhow!("No link found in row"))?
+            .text()
+            .parse()?,
+        failures: badge(row, "badge-danger")?,
+        successes: badge(row, "badge-success")?,
+        queued: badge(row, "badge-secondary")?,
+        time: time
+            .attr("title")
+            .ok_or_else(|| anyhow!("No time found"))?
+            .to_string(),
+        datetime,
+        nixpkgs_change: nixpkgs_change(row),
+    })
+}
+
+/// How many evaluations with the same abbreviated commit are checked for the full one
+const MAX_COMMIT_CANDIDATES: usize = 4;
+
+/// Which evaluation of a jobset to pick
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalPolicy {
+    /// The latest evaluation whose builds all finished (`latest`)
+    LatestFinished,
+    /// The latest evaluation with at least this percentage of finished builds (`finished:90`)
+    MinFinished(f64),
+    /// The evaluation with this ID (`id:1001`)
+    Id(u64),
+    /// The evaluation that moved `nixpkgs` to this commit, given in full or abbreviated
+    /// (`commit:a1b2c3d`)
+    Commit(String),
+    /// The evaluation that happened closest to this time (`at:2023-05-10T12:00:00Z`)
+    ClosestTo(DateTime<Utc>),
+}
+
+impl FromStr for EvalPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "latest" {
+            return Ok(EvalPolicy::LatestFinished);
+        }
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Unknown eval policy {s:?}"))?;
+        match kind {
+            "finished" => {
+                let percent: f64 = value
+                    .trim_end_matches('%')
+                    .parse()
+                    .with_context(|| format!("Invalid percentage {value:?}"))?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(anyhow!("Percentage {percent} is not between 0 and 100"));
+                }
+                Ok(EvalPolicy::MinFinished(percent))
+            }
+            "id" => Ok(EvalPolicy::Id(
+                value
+                    .parse()
+                    .with_context(|| format!("Invalid eval ID {value:?}"))?,
+            )),
+            "commit" => {
+                if value.is_empty() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(anyhow!("Invalid commit {value:?}"));
+                }
+                Ok(EvalPolicy::Commit(value.to_ascii_lowercase()))
+            }
+            "at" => Ok(EvalPolicy::ClosestTo(
+                DateTime::parse_from_rfc3339(value)
+                    .with_context(|| format!("Invalid time {value:?}"))?
+                    .with_timezone(&Utc),
+            )),
+            _ => Err(anyhow!("Unknown eval policy {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for EvalPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalPolicy::LatestFinished => write!(f, "latest"),
+            EvalPolicy::MinFinished(percent) => write!(f, "finished:{percent}"),
+            EvalPolicy::Id(id) => write!(f, "id:{id}"),
+            EvalPolicy::Commit(commit) => write!(f, "commit:{commit}"),
+            EvalPolicy::ClosestTo(time) => {
+                write!(f, "at:{}", time.to_rfc3339_opts(SecondsFormat::Secs, true))
+            }
+        }
+    }
+}
+
+/// The abbreviated `nixpkgs` revision of an evaluation row, if the evaluation changed it
+fn nixpkgs_change(row: &Node) -> Option<String> {
+    row.find(Name("tt"))
+        .find(|tt| {
+            tt.prev()
+                .is_some_and(|prev| prev.text().trim_end().ends_with("nixpkgs →"))
+        })
+        .map(|tt| tt.text().trim().to_ascii_lowercase())
+}
+
+/// The full `nixpkgs` revision of an evaluation, if it has that input
+pub async fn nixpkgs_revision(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    eval_id: u64,
+) -> Result<Option<String>> {
+    let url = format!("{hydra_url}/eval/{eval_id}");
+    let res = http_client
+        .get(&url)
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let hydra_eval: HydraEval =
+        serde_json::from_str(&res).with_context(|| format!("Invalid JSON from {url}"))?;
+    Ok(hydra_eval
+        .jobsetevalinputs
+        .get("nixpkgs")
+        .and_then(|input| input.revision.clone()))
+}
+
+/// Finds the latest evaluation of a jobset whose builds all finished
+pub async fn latest_finished_eval(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+) -> Result<JobsetEval> {
+    select_eval(
+        http_client,
+        hydra_url,
+        project,
+        jobset,
+        &EvalPolicy::LatestFinished,
+    )
+    .await
+}
+
+/// A page of the evaluations of a jobset, newest first
+struct EvalPage {
+    evals: Vec<JobsetEval>,
+    /// Whether there are older evaluations on the next page
+    has_next: bool,
+}
+
+/// Fetches a page (starting at 1) of the evaluations of a jobset
+async fn fetch_eval_page(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+    page: u32,
+) -> Result<EvalPage> {
+    let mut url = format!("{hydra_url}/jobset/{project}/{jobset}/evals");
+    if page > 1 {
+        url.push_str(&format!("?page={page}"));
+    }
+    let res = http_client
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    // Parse output
+    let doc = select::document::Document::from(&res[..]);
+    let eval_table = doc
+        .find(Name("tbody"))
+        .next()
+        .ok_or_else(|| anyhow!("No evaluation table found in {url}"))?;
+    let evals = eval_table
+        .find(Name("tr"))
+        .map(|row| parse_row(&row))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("Failed parsing {url}"))?;
+    // The link to the next page is disabled on the last one
+    let has_next = doc
+        .find(Class("pagination").descendant(Name("a")))
+        .any(|link| {
+            link.text().contains("Next")
+                && link.attr("href").is_some_and(|href| href.contains("page="))
+        });
+    Ok(EvalPage { evals, has_next })
+}
+
+/// How many pages of evaluations [`select_eval`] walks before it gives up
+pub const MAX_EVAL_PAGES: u32 = 20;
+
+/// Walks the evaluations of a jobset from the newest to the oldest, page by page, until `visit`
+/// returns false or there are no more evaluations. Fails after `max_pages` pages, if given.
+async fn walk_evals(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+    max_pages: Option<u32>,
+    mut visit: impl FnMut(JobsetEval) -> bool,
+) -> Result<()> {
+    let mut page = 1;
+    loop {
+        let EvalPage { evals, has_next } =
+            fetch_eval_page(http_client, hydra_url, project, jobset, page).await?;
+        for eval in evals {
+            if !visit(eval) {
+                return Ok(());
+            }
+        }
+        if !has_next {
+            return Ok(());
+        }
+        if max_pages == Some(page) {
+            return Err(anyhow!(
+                "Gave up searching the evals of {project}:{jobset} after {page} pages"
+            ));
+        }
+        page += 1;
+    }
+}
+
+/// Lists the `count` latest evaluations of a jobset, newest first, including unfinished ones
+pub async fn list_evals(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+    count: usize,
+) -> Result<Vec<JobsetEval>> {
+    let mut evals = vec![];
+    if count == 0 {
+        return Ok(evals);
+    }
+    walk_evals(http_client, hydra_url, project, jobset, None, |eval| {
+        evals.push(eval);
+        evals.len() < count
+    })
+    .await?;
+    Ok(evals)
+}
+
+/// Lists the evaluations of a jobset that happened between `from` and `to`, newest first,
+/// including unfinished ones
+pub async fn evals_between(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<JobsetEval>> {
+    let mut evals = vec![];
+    walk_evals(http_client, hydra_url, project, jobset, None, |eval| {
+        let Ok(datetime) = eval.datetime.parse::<DateTime<Utc>>() else {
+            return true;
+        };
+        if datetime < from {
+            return false;
+        }
+        if datetime <= to {
+            evals.push(eval);
+        }
+        true
+    })
+    .await?;
+    Ok(evals)
+}
+
+/// Finds the evaluation of a jobset picked by a policy
+pub async fn select_eval(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    project: &str,
+    jobset: &str,
+    policy: &EvalPolicy,
+) -> Result<JobsetEval> {
+    // Candidates are collected first, since checking a commit needs another request
+    let mut candidates = vec![];
+    let mut closest: Option<(i64, JobsetEval)> = None;
+    // Unlike listing, a search may not find anything before the oldest evaluation
+    let max_pages = Some(MAX_EVAL_PAGES);
+    walk_evals(http_client, hydra_url, project, jobset, max_pages, |eval| {
+        match policy {
+            EvalPolicy::LatestFinished => {
+                // Skip evals with unfinished builds and fully failed evals (no builds)
+                if eval.is_finished() && eval.successes > 0 {
+                    candidates.push(eval);
+                }
+            }
+            EvalPolicy::MinFinished(percent) => {
+                let finished = eval.failures + eval.successes;
+                let total = finished + eval.queued;
+                if total > 0 && finished as f64 * 100.0 >= percent * total as f64 {
+                    candidates.push(eval);
+                }
+            }
+            EvalPolicy::Id(id) => {
+                // Evaluations are listed by descending ID
+                if eval.id < *id {
+                    return false;
+                }
+                if eval.id == *id {
+                    candidates.push(eval);
+                }
+            }
+            EvalPolicy::Commit(commit) => {
+                let matches = eval.nixpkgs_change.as_ref().is_some_and(|short| {
+                    commit.starts_with(short.as_str()) || short.starts_with(commit.as_str())
+                });
+                if matches {
+                    candidates.push(eval);
+                }
+                // The abbreviation shown by Hydra may be ambiguous, so look further
+                return candidates.len() < MAX_COMMIT_CANDIDATES;
+            }
+            EvalPolicy::ClosestTo(time) => {
+                let Ok(datetime) = eval.datetime.parse::<DateTime<Utc>>() else {
+                    return true;
+                };
+                let distance = (datetime - *time).num_seconds().abs();
+                if closest.as_ref().is_none_or(|(best, _)| distance < *best) {
+                    closest = Some((distance, eval));
+                }
+                // Older evaluations are only further away
+                return datetime > *time;
+            }
+        }
+        candidates.is_empty()
+    })
+    .await?;
+
+    if let EvalPolicy::Commit(commit) = policy {
+        for eval in candidates {
+            let short_len = eval.nixpkgs_change.as_ref().map_or(0, String::len);
+            if commit.len() <= short_len {
+                return Ok(eval);
+            }
+            let revision = nixpkgs_revision(http_client, hydra_url, eval.id).await?;
+            if revision.is_some_and(|revision| revision.starts_with(commit.as_str())) {
+                return Ok(eval);
+            }
+        }
+    } else if let Some(eval) = candidates.into_iter().next() {
+        return Ok(eval);
+    }
+    closest
+        .map(|(_, eval)| eval)
+        .ok_or_else(|| anyhow!("No eval of {project}:{jobset} matches {policy}"))
+}
@@ -1,68 +1,95 @@
-//! Crawl some data about the latest finished evaluation from the Hydra web interface directly.
-//! We need to do this because the API doesn't offer this data.
+//! Print an evaluation of a jobset as `{eval} {failures} {time}`, or as JSON with
+//! `--format json`
+//!
+//! Usage: `crawl_jobset [--offline] [--format text|json] [--eval policy | --last N] project jobset`
+//!
+//! With `--last N`, the N latest evaluations are printed instead, newest first and including
+//! unfinished ones, one per line or as a JSON array.
+//!
+//! The policy picks the evaluation:
+//! - `latest`: the latest evaluation whose builds all finished (the default)
+//! - `finished:N`: the latest evaluation with at least N% of its builds finished
+//! - `id:N`: the evaluation N
+//! - `commit:SHA`: the evaluation that moved `nixpkgs` to a commit
+//! - `at:TIME`: the evaluation closest to an RFC 3339 time
 
-use anyhow::{anyhow, Result};
-use reqwest_middleware::ClientBuilder;
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use select::predicate::{Class, Name};
+use anyhow::{anyhow, Context, Result};
+use crawl_jobset::{EvalPolicy, Format};
+
+const USAGE: &str =
+    "Usage: crawl_jobset [--offline] [--format text|json] [--eval policy | --last N] project jobset";
+
+/// Removes `name value` from the arguments, returning the value
+fn take_option(argv: &mut Vec<String>, name: &str) -> Result<Option<String>> {
+    let Some(i) = argv.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    let value = argv
+        .get(i + 1)
+        .ok_or_else(|| anyhow!("{name} needs a value"))?
+        .clone();
+    argv.drain(i..=i + 1);
+    Ok(Some(value))
+}
 
 #[tokio::main(worker_threads = 4)]
 async fn main() -> Result<()> {
     env_logger::builder().format_timestamp(None).init();
-    let argv: Vec<String> = std::env::args().collect();
-    let project = &argv[1];
-    let jobset = &argv[2];
-
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(10);
-    let http_client = ClientBuilder::new(reqwest::Client::new())
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
+    let mut argv: Vec<String> = std::env::args().collect();
+    let offline = hydra_client::take_offline_flag(&mut argv);
+    let format = match take_option(&mut argv, "--format")? {
+        Some(format) => format.parse()?,
+        None => Format::Text,
+    };
+    let policy = take_option(&mut argv, "--eval")?;
+    let last = take_option(&mut argv, "--last")?;
+    if policy.is_some() && last.is_some() {
+        return Err(anyhow!("--eval and --last can't be combined"));
+    }
+    let [_, project, jobset] = &argv[..] else {
+        return Err(anyhow!(USAGE));
+    };
 
-    let res = http_client
-        .get(format!(
-            "https://hydra.nixos.org/jobset/{project}/{jobset}/evals"
-        ))
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-    // Parse output
-    let doc = select::document::Document::from(&res[..]);
-    let eval_table = doc
-        .find(Name("tbody"))
-        .next()
-        .ok_or_else(|| anyhow!("No evaluation table found"))?;
-    let eval_rows = eval_table.find(Name("tr"));
-    for row in eval_rows {
-        // Skip evals with unfinished builds
-        if row.find(Class("badge-secondary")).next().is_some() {
-            continue;
+    let config = zhf_core::Config::load()?;
+    let branch = config.branch(None)?.clone();
+    let data_dir = std::env::current_dir()?.join("data");
+    let (http_client, metrics) = hydra_client::client(&config.http, &data_dir, offline)?;
+    let hydra_url = branch.hydra_url();
+    if let Some(last) = last {
+        let count = last
+            .parse()
+            .with_context(|| format!("Invalid number of evals {last:?}"))?;
+        let evals =
+            crawl_jobset::list_evals(&http_client, &hydra_url, project, jobset, count).await?;
+        match format {
+            Format::Text => {
+                for eval in &evals {
+                    println!("{eval}");
+                }
+            }
+            Format::Json => {
+                let mut summaries = vec![];
+                for eval in &evals {
+                    summaries.push(crawl_jobset::summarize(&http_client, &hydra_url, eval).await?);
+                }
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            }
         }
-        // Skip fully failed evals (no builds)
-        if row.find(Class("badge-success")).next().is_none() {
-            continue;
+    } else {
+        let policy = match policy {
+            Some(policy) => policy.parse()?,
+            None => EvalPolicy::LatestFinished,
+        };
+        let eval =
+            crawl_jobset::select_eval(&http_client, &hydra_url, project, jobset, &policy).await?;
+        match format {
+            Format::Text => println!("{eval}"),
+            Format::Json => {
+                let summary = crawl_jobset::summarize(&http_client, &hydra_url, &eval).await?;
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            }
         }
-
-        println!(
-            "{} {} {}",
-            row.find(Name("a"))
-                .next()
-                .ok_or_else(|| anyhow!("No link found in row"))?
-                .text(),
-            row.find(Class("badge-danger"))
-                .next()
-                .map(|x| x.text())
-                .unwrap_or_else(|| "0".to_string()),
-            row.find(Name("time"))
-                .next()
-                .ok_or_else(|| anyhow!("No time found"))?
-                .attr("title")
-                .ok_or_else(|| anyhow!("No time found"))?
-        );
-        return Ok(());
     }
-
-    log::error!("No finished eval found");
-    Err(anyhow!("No finished eval found"))
+    log::info!("Hydra requests: {metrics}");
+    Ok(())
 }
@@ -0,0 +1,150 @@
+//! Run `crawl_jobset` against recorded Hydra pages
+
+use hydra_fixtures::{pages_dir, FixtureServer};
+use std::path::Path;
+use std::process::Command;
+
+fn crawl_jobset(args: &[&str]) -> String {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    // Answers are cached below the working directory
+    let work_dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_crawl_jobset"))
+        .args(args)
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "crawl_jobset failed: {output:?}");
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn golden(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn skips_unfinished_evals() {
+    assert_eq!(
+        crawl_jobset(&["nixos", "trunk-combined"]),
+        golden("nixos-trunk-combined.txt")
+    );
+}
+
+#[test]
+fn skips_evals_without_successful_builds() {
+    assert_eq!(
+        crawl_jobset(&["nixpkgs", "trunk"]),
+        golden("nixpkgs-trunk.txt")
+    );
+}
+
+#[test]
+fn prints_json() {
+    assert_eq!(
+        crawl_jobset(&["--format", "json", "nixos", "trunk-combined"]),
+        golden("nixos-trunk-combined.json")
+    );
+}
+
+#[test]
+fn selects_evals_by_policy() {
+    let eval_id = |policy: &str, jobset: [&str; 2]| {
+        let line = crawl_jobset(&["--eval", policy, jobset[0], jobset[1]]);
+        line.split(' ').next().unwrap().to_string()
+    };
+    let nixos = ["nixos", "trunk-combined"];
+    assert_eq!(eval_id("latest", nixos), "1001");
+    assert_eq!(eval_id("finished:5", nixos), "1002");
+    assert_eq!(eval_id("finished:50", nixos), "1001");
+    assert_eq!(eval_id("id:1002", nixos), "1002");
+    assert_eq!(eval_id("at:2023-05-11T00:00:00Z", nixos), "1002");
+    assert_eq!(eval_id("at:2023-05-10T12:00:00+02:00", nixos), "1001");
+    // On the second page
+    assert_eq!(eval_id("id:1000", nixos), "1000");
+    assert_eq!(eval_id("at:2023-05-07T00:00:00Z", nixos), "999");
+    assert_eq!(eval_id("commit:8e7d6c5", nixos), "999");
+
+    let nixpkgs = ["nixpkgs", "trunk"];
+    assert_eq!(eval_id("commit:b2c3d4e", nixpkgs), "2001");
+    assert_eq!(
+        eval_id("commit:a1b2c3d4e5f6a1b2c3d4e5f6a1b2c3d4e5f6a1b2", nixpkgs),
+        "2000"
+    );
+}
+
+#[test]
+fn fails_without_a_matching_eval() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_crawl_jobset"))
+        .args(["--eval", "commit:ffffff", "nixpkgs", "trunk"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("No eval of nixpkgs:trunk matches commit:ffffff"));
+}
+
+#[test]
+fn only_searches_a_bounded_number_of_pages() {
+    // More pages than a search walks, all but the last one link to a next one
+    let pages = tempfile::tempdir().unwrap();
+    let evals_dir = pages.path().join("jobset/nixos/trunk-combined");
+    std::fs::create_dir_all(&evals_dir).unwrap();
+    let fixtures = pages_dir().join("jobset/nixos/trunk-combined");
+    std::fs::copy(fixtures.join("evals.html"), evals_dir.join("evals.html")).unwrap();
+    let last_page = crawl_jobset::MAX_EVAL_PAGES + 2;
+    for page in 2..=last_page {
+        let fixture = if page == last_page {
+            "evals@page=2.html"
+        } else {
+            "evals.html"
+        };
+        let target = evals_dir.join(format!("evals@page={page}.html"));
+        std::fs::copy(fixtures.join(fixture), target).unwrap();
+    }
+    let server = FixtureServer::start(pages.path()).unwrap();
+    let run = |args: &[&str]| {
+        let work_dir = tempfile::tempdir().unwrap();
+        Command::new(env!("CARGO_BIN_EXE_crawl_jobset"))
+            .args(args)
+            .env("HYDRA_URL", server.url())
+            .current_dir(work_dir.path())
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["--eval", "commit:ffffff", "nixos", "trunk-combined"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Gave up searching the evals of nixos:trunk-combined after 20 pages"));
+
+    // Listing walks to the end, every page lists two evaluations
+    let output = run(&["--last", "1000", "nixos", "trunk-combined"]);
+    assert!(output.status.success(), "crawl_jobset failed: {output:?}");
+    let evals = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(evals.lines().count(), 2 * last_page as usize);
+}
+
+#[test]
+fn lists_the_latest_evals_across_pages() {
+    assert_eq!(
+        crawl_jobset(&["--last", "3", "nixos", "trunk-combined"]),
+        "1002 1 2023-05-11 08:00:00 (UTC)\n\
+         1001 4 2023-05-10 09:01:02 (UTC)\n\
+         1000 2 2023-05-08 18:30:00 (UTC)\n"
+    );
+    // Stops at the last page
+    assert_eq!(
+        crawl_jobset(&["--last", "10", "nixos", "trunk-combined"])
+            .lines()
+            .count(),
+        4
+    );
+}
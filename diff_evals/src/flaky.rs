@@ -0,0 +1,88 @@
+//! Attributes whose builds flip between succeeding and failing without their derivation changing,
+//! which almost always means the build is flaky
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use zhf_core::{Build, Store, StoredEval};
+
+/// How many of the latest recorded evaluations of a jobset are looked at
+pub const FLAKY_WINDOW: usize = 10;
+
+/// An attribute that is probably flaky
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlakyAttr {
+    pub attr: String,
+    /// The build in the evaluation that was looked at
+    pub build: Build,
+    /// How often the status flipped without the derivation changing
+    pub flips: usize,
+    /// The latest evaluation the status flipped in
+    pub last_flip: StoredEval,
+}
+
+/// Whether two builds of an attribute are known to build the same derivation
+fn same_derivation(old: &Build, new: &Build) -> bool {
+    // Hydra reuses the build of an unchanged derivation, restarting it changes the status
+    old.id == new.id || (old.drv_path.is_some() && old.drv_path == new.drv_path)
+}
+
+/// The attributes of an evaluation recorded in the store whose status flipped without their
+/// derivation changing, in the last `FLAKY_WINDOW` evaluations of its jobset up to this one.
+/// Sorted by attribute.
+pub fn flaky_attrs(store: &Store, eval_id: u64) -> Result<Vec<FlakyAttr>> {
+    let Some(eval) = store.stored_eval(eval_id)? else {
+        return Ok(vec![]);
+    };
+    let mut window = vec![];
+    for stored in store
+        .evals(&eval.branch, &eval.jobset)?
+        .into_iter()
+        .filter(|stored| stored.id <= eval_id)
+        .rev()
+    {
+        if window.len() == FLAKY_WINDOW {
+            break;
+        }
+        if let Some(builds) = store.eval(stored.id)? {
+            window.push((stored, builds));
+        }
+    }
+    window.reverse();
+
+    let mut flipped: BTreeMap<&str, (usize, &StoredEval)> = BTreeMap::new();
+    for pair in window.windows(2) {
+        let old: HashMap<&str, &Build> = pair[0]
+            .1
+            .builds
+            .iter()
+            .map(|build| (build.attr.as_str(), build))
+            .collect();
+        for new in &pair[1].1.builds {
+            let Some(old) = old.get(new.attr.as_str()) else {
+                continue;
+            };
+            if old.status.flips_to(&new.status) && same_derivation(old, new) {
+                let entry = flipped.entry(&new.attr).or_insert((0, &pair[1].0));
+                entry.0 += 1;
+                entry.1 = &pair[1].0;
+            }
+        }
+    }
+
+    let Some((_, latest)) = window.last().filter(|(stored, _)| stored.id == eval_id) else {
+        return Ok(vec![]);
+    };
+    Ok(latest
+        .builds
+        .iter()
+        .filter_map(|build| {
+            let (flips, last_flip) = flipped.get(build.attr.as_str())?;
+            Some(FlakyAttr {
+                attr: build.attr.clone(),
+                build: build.clone(),
+                flips: *flips,
+                last_flip: (*last_flip).clone(),
+            })
+        })
+        .collect())
+}
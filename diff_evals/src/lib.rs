@@ -0,0 +1,293 @@
+//! Compare the builds of two evaluations and render what changed
+
+mod flaky;
+
+pub use flaky::{flaky_attrs, FlakyAttr, FLAKY_WINDOW};
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+use zhf_core::{escape_html, Build, Eval, Store, StoredEval};
+
+/// How an attribute changed between two evaluations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    /// Succeeded before, fails now
+    NewlyFailing,
+    /// Failed before, succeeds now
+    Fixed,
+    /// Failed before and still fails
+    StillFailing,
+    /// Not in the old evaluation
+    Added,
+    /// Not in the new evaluation
+    Removed,
+    /// Succeeded before and still succeeds
+    StillSucceeding,
+}
+
+impl Change {
+    /// All changes in the order they are listed in
+    pub const ALL: [Change; 6] = [
+        Change::NewlyFailing,
+        Change::Fixed,
+        Change::StillFailing,
+        Change::Added,
+        Change::Removed,
+        Change::StillSucceeding,
+    ];
+
+    /// Heading of the section listing this change
+    fn title(&self) -> &'static str {
+        match self {
+            Change::NewlyFailing => "Newly failing",
+            Change::Fixed => "Fixed",
+            Change::StillFailing => "Still failing",
+            Change::Added => "Added",
+            Change::Removed => "Removed",
+            Change::StillSucceeding => "Still succeeding",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::NewlyFailing => "newly-failing",
+            Change::Fixed => "fixed",
+            Change::StillFailing => "still-failing",
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::StillSucceeding => "still-succeeding",
+        })
+    }
+}
+
+/// The change of a single attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttrChange {
+    pub attr: String,
+    pub change: Change,
+    /// The build in the old evaluation
+    pub old: Option<Build>,
+    /// The build in the new evaluation
+    pub new: Option<Build>,
+    /// For attributes that are still failing, the evaluation since which the attribute failed in
+    /// every evaluation, if the history is known
+    pub failing_since: Option<StoredEval>,
+}
+
+impl AttrChange {
+    /// The build this change is about, preferring the new one
+    pub fn build(&self) -> &Build {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .expect("A change has at least one build")
+    }
+}
+
+impl fmt::Display for AttrChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} -> {}",
+            self.change,
+            self.attr,
+            status_of(&self.old),
+            status_of(&self.new)
+        )?;
+        if let Some(since) = &self.failing_since {
+            write!(f, " (since {}, {})", since.id, since.time)?;
+        }
+        Ok(())
+    }
+}
+
+/// The status of a build, `-` if there is no build
+fn status_of(build: &Option<Build>) -> String {
+    match build {
+        Some(build) => build.status.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// Every attribute of two evaluations, classified by how it changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalDiff {
+    pub old_id: u64,
+    pub new_id: u64,
+    /// Sorted by attribute
+    pub changes: Vec<AttrChange>,
+}
+
+impl EvalDiff {
+    /// The attributes that changed in the given way
+    pub fn of(&self, change: Change) -> impl Iterator<Item = &AttrChange> {
+        self.changes.iter().filter(move |c| c.change == change)
+    }
+}
+
+/// Classifies every attribute of both evaluations
+pub fn diff(old: &Eval, new: &Eval) -> EvalDiff {
+    let mut builds: BTreeMap<&str, (Option<&Build>, Option<&Build>)> = BTreeMap::new();
+    for build in &old.builds {
+        builds.entry(&build.attr).or_default().0 = Some(build);
+    }
+    for build in &new.builds {
+        builds.entry(&build.attr).or_default().1 = Some(build);
+    }
+
+    let changes = builds
+        .into_iter()
+        .map(|(attr, (old, new))| {
+            let change = match (old, new) {
+                (None, _) => Change::Added,
+                (_, None) => Change::Removed,
+                (Some(old), Some(new)) => {
+                    match (old.status.is_failure(), new.status.is_failure()) {
+                        (false, true) => Change::NewlyFailing,
+                        (true, false) => Change::Fixed,
+                        (true, true) => Change::StillFailing,
+                        (false, false) => Change::StillSucceeding,
+                    }
+                }
+            };
+            AttrChange {
+                attr: attr.to_string(),
+                change,
+                old: old.cloned(),
+                new: new.cloned(),
+                failing_since: None,
+            }
+        })
+        .collect();
+    EvalDiff {
+        old_id: old.id,
+        new_id: new.id,
+        changes,
+    }
+}
+
+/// Compares two evaluations recorded in the store. If the new evaluation's jobset is known, the
+/// attributes that are still failing are annotated with when they started failing.
+pub fn diff_stored(store: &Store, old_id: u64, new_id: u64) -> Result<EvalDiff> {
+    let load = |id| {
+        store
+            .eval(id)?
+            .ok_or_else(|| anyhow!("The builds of evaluation {id} are not in the store"))
+    };
+    let mut diff = diff(&load(old_id)?, &load(new_id)?);
+
+    if let Some(new) = store.stored_eval(new_id)? {
+        let mut since = store.failing_since(&new)?;
+        for change in &mut diff.changes {
+            if change.change == Change::StillFailing {
+                change.failing_since = since.remove(&change.attr);
+            }
+        }
+    }
+    Ok(diff)
+}
+
+/// Compares an evaluation recorded in the store to the previous evaluation of its jobset, if
+/// there is one
+pub fn diff_with_previous(store: &Store, eval_id: u64) -> Result<Option<EvalDiff>> {
+    let eval = store
+        .stored_eval(eval_id)?
+        .ok_or_else(|| anyhow!("Evaluation {eval_id} is not in the store"))?;
+    match store.previous_eval(&eval)? {
+        Some(previous) => Ok(Some(diff_stored(store, previous.id, eval_id)?)),
+        None => Ok(None),
+    }
+}
+
+/// Renders the page with what changed since the previous evaluation of each jobset, given by
+/// its title
+pub fn render_changes_page(
+    hydra_url: &str,
+    site_url: &str,
+    diffs: &[(&str, Option<EvalDiff>)],
+    out: &Path,
+) -> Result<()> {
+    let mut out = File::create(out)?;
+    out.write_fmt(format_args!(r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <meta http-equiv="X-UA-Compatible" content="ie=edge">
+        <title>Hydra changes</title>
+        <link rel="stylesheet" href="style.css">
+        <link rel="icon" type="image/x-icon" href="favicon.ico">
+        <meta property="og:title" content="What changed on Hydra" />
+        <meta property="og:description" content="Builds that broke or were fixed since the last evaluation" />
+        <meta property="og:type" content="website" />
+        <meta property="og:url" content="{site_url}/changes.html" />
+        <meta property="og:image" content="icon.png" />
+      </head>
+      <body>
+        <h1><a href="index.html" title="Go Home"><img src="nix-snowflake.svg"></a>What changed since the last evaluation</h1>"#))?;
+
+    for (title, diff) in diffs {
+        out.write_fmt(format_args!("<h2>{title}</h2>"))?;
+        let Some(diff) = diff else {
+            out.write_fmt(format_args!("<p>No earlier evaluation is recorded.</p>"))?;
+            continue;
+        };
+        let (old_id, new_id) = (diff.old_id, diff.new_id);
+        out.write_fmt(format_args!(r#"<p>From evaluation <a href="{hydra_url}/eval/{old_id}">{old_id}</a> to <a href="{hydra_url}/eval/{new_id}">{new_id}</a>:
+        <b>{}</b> newly failing, <b>{}</b> fixed, <b>{}</b> still failing, <b>{}</b> added, <b>{}</b> removed.</p>"#,
+            diff.of(Change::NewlyFailing).count(),
+            diff.of(Change::Fixed).count(),
+            diff.of(Change::StillFailing).count(),
+            diff.of(Change::Added).count(),
+            diff.of(Change::Removed).count(),
+        ))?;
+
+        for change in Change::ALL {
+            if change == Change::StillSucceeding {
+                continue;
+            }
+            let section_title = change.title();
+            let since_header = if change == Change::StillFailing {
+                "<th>Failing since</th>"
+            } else {
+                ""
+            };
+            out.write_fmt(format_args!(r#"<h3>{section_title}</h3>
+            <table>
+              <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Before</th><th>Now</th>{since_header}</tr></thead>
+              <tbody>"#))?;
+            let mut found = false;
+            for attr_change in diff.of(change) {
+                found = true;
+                let build = attr_change.build();
+                out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>", build.id, escape_html(&attr_change.attr), escape_html(&build.name), build.system, escape_html(&status_of(&attr_change.old)), escape_html(&status_of(&attr_change.new))))?;
+                if change == Change::StillFailing {
+                    match &attr_change.failing_since {
+                        Some(since) => out.write_fmt(format_args!(
+                            "<td><a href=\"{hydra_url}/eval/{}\">{}</a> ({})</td>",
+                            since.id, since.id, since.time
+                        ))?,
+                        None => out.write_fmt(format_args!("<td>unknown</td>"))?,
+                    }
+                }
+                out.write_fmt(format_args!("</tr>"))?;
+            }
+            if !found {
+                let columns = if change == Change::StillFailing { 6 } else { 5 };
+                out.write_fmt(format_args!(
+                    r#"<tr><td colspan="{columns}" class="none">None</td></tr>"#
+                ))?;
+            }
+            out.write_fmt(format_args!("</tbody></table>"))?;
+        }
+    }
+
+    out.write_fmt(format_args!("</body></html>"))?;
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! Print how every attribute changed between two evaluations recorded in the store
+//!
+//! Usage: `diff_evals old_eval new_eval`. Attributes that succeeded in both are left out.
+
+use anyhow::{anyhow, Result};
+use diff_evals::Change;
+
+fn main() -> Result<()> {
+    env_logger::builder().format_timestamp(None).init();
+    // Handle args
+    let argv: Vec<u64> = std::env::args()
+        .skip(1)
+        .map(|x| x.parse::<u64>())
+        .collect::<Result<_, _>>()?;
+    let [old, new] = argv[..] else {
+        return Err(anyhow!("Usage: diff_evals old_eval new_eval"));
+    };
+
+    let mut data_dir = std::env::current_dir()?;
+    data_dir.push("data");
+    let store = zhf_core::Store::open_in(&data_dir)?;
+
+    let diff = diff_evals::diff_stored(&store, old, new)?;
+    for change in &diff.changes {
+        if change.change != Change::StillSucceeding {
+            println!("{change}");
+        }
+    }
+    Ok(())
+}
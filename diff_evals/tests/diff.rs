@@ -0,0 +1,174 @@
+//! Compare evaluations recorded in a store
+
+use std::path::Path;
+use std::process::Command;
+use zhf_core::{Eval, Store, StoredEval};
+
+/// Records an evaluation of the `linux` jobset of `master` with builds given as lines of the eval
+/// cache
+fn record_lines(store: &Store, id: u64, lines: &[String]) {
+    store
+        .record_eval(&StoredEval {
+            id,
+            branch: "master".to_string(),
+            jobset: "linux".to_string(),
+            failures: 0,
+            time: format!("2023-05-{:02} 00:00:00 (UTC)", id - 990),
+        })
+        .unwrap();
+    let builds = lines.iter().map(|line| line.parse().unwrap()).collect();
+    store.record_builds(&Eval::new(id, builds)).unwrap();
+}
+
+/// Records an evaluation of the `linux` jobset of `master` with builds given as `attr status`
+fn record(store: &Store, id: u64, builds: &[(&str, &str)]) {
+    let lines: Vec<String> = builds
+        .iter()
+        .enumerate()
+        .map(|(i, (attr, status))| {
+            format!(
+                "{attr}.x86_64-linux {}{i} {attr}-1.0 x86_64-linux {status}",
+                id * 10
+            )
+        })
+        .collect();
+    record_lines(store, id, &lines);
+}
+
+fn diff_evals(work_dir: &Path, old: &str, new: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_diff_evals"))
+        .args([old, new])
+        .current_dir(work_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn classifies_every_attr() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    record(
+        &store,
+        998,
+        &[("a", "Failed"), ("b", "Succeeded"), ("c", "Failed")],
+    );
+    record(
+        &store,
+        999,
+        &[
+            ("a", "Failed"),
+            ("b", "Failed"),
+            ("c", "Succeeded"),
+            ("d", "Succeeded"),
+            ("e", "Failed"),
+            ("r", "Succeeded"),
+        ],
+    );
+    record(
+        &store,
+        1000,
+        &[
+            ("a", "Failed"),
+            ("b", "Dependency failed"),
+            ("c", "Failed"),
+            ("d", "Succeeded"),
+            ("e", "Succeeded"),
+            ("n", "Timed out"),
+        ],
+    );
+
+    assert_eq!(
+        diff_evals(work_dir.path(), "999", "1000"),
+        "still-failing a.x86_64-linux Failed -> Failed (since 998, 2023-05-08 00:00:00 (UTC))\n\
+         still-failing b.x86_64-linux Failed -> Dependency failed (since 999, 2023-05-09 00:00:00 (UTC))\n\
+         newly-failing c.x86_64-linux Succeeded -> Failed\n\
+         fixed e.x86_64-linux Failed -> Succeeded\n\
+         added n.x86_64-linux - -> Timed out\n\
+         removed r.x86_64-linux Succeeded -> -\n"
+    );
+}
+
+#[test]
+fn fails_for_unknown_evals() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_diff_evals"))
+        .args(["1", "2"])
+        .current_dir(work_dir.path())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn finds_flaky_attrs() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(work_dir.path()).unwrap();
+    // Builds given as `attr build_id status drv`
+    let record = |id, builds: &[(&str, u64, &str, &str)]| {
+        let lines: Vec<String> = builds
+            .iter()
+            .map(|(attr, build_id, status, drv)| {
+                format!("{attr} {build_id} {attr}-1.0 x86_64-linux {status} /nix/store/{drv}.drv")
+            })
+            .collect();
+        record_lines(&store, id, &lines);
+    };
+    record(
+        998,
+        &[
+            ("same-drv", 1, "Succeeded", "a"),
+            ("changed-drv", 2, "Succeeded", "b"),
+            ("restarted", 3, "Failed", "c"),
+            ("dependency", 4, "Succeeded", "d"),
+        ],
+    );
+    record(
+        999,
+        &[
+            ("same-drv", 11, "Failed", "a"),
+            ("changed-drv", 12, "Failed", "b2"),
+            ("restarted", 3, "Succeeded", "c"),
+            ("dependency", 14, "Dependency failed", "d"),
+        ],
+    );
+    record(
+        1000,
+        &[
+            ("same-drv", 21, "Succeeded", "a"),
+            ("changed-drv", 12, "Failed", "b2"),
+            ("restarted", 3, "Succeeded", "c"),
+            ("dependency", 14, "Dependency failed", "d"),
+        ],
+    );
+
+    let flaky = diff_evals::flaky_attrs(&store, 1000).unwrap();
+    let flaky: Vec<(&str, usize, u64)> = flaky
+        .iter()
+        .map(|attr| (attr.attr.as_str(), attr.flips, attr.last_flip.id))
+        .collect();
+    assert_eq!(flaky, [("restarted", 1, 999), ("same-drv", 2, 1000)]);
+    assert!(diff_evals::flaky_attrs(&store, 998).unwrap().is_empty());
+}
+
+#[test]
+fn escapes_the_changes_page() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(work_dir.path()).unwrap();
+    record(&store, 1000, &[("a<b>", "Succeeded")]);
+    record(&store, 1001, &[("a<b>", "Failed")]);
+
+    let diff = diff_evals::diff_stored(&store, 1000, 1001).unwrap();
+    let out = work_dir.path().join("changes.html");
+    diff_evals::render_changes_page(
+        "https://hydra.nixos.org",
+        "https://zh.fail",
+        &[("Linux", Some(diff))],
+        &out,
+    )
+    .unwrap();
+    let page = std::fs::read_to_string(out).unwrap();
+    assert!(page.contains(">a&lt;b&gt;.x86_64-linux</a></td><td>a&lt;b&gt;-1.0</td>"));
+    assert!(!page.contains("a<b>"));
+}
@@ -0,0 +1,36 @@
+//! Keeps a shallow nixpkgs checkout below `data/nixpkgs`
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::create_dir_all;
+use std::path::Path;
+use std::process::Command;
+
+const NIXPKGS_URL: &str = "https://github.com/NixOS/nixpkgs.git";
+
+/// Runs git with the given arguments inside `repo`
+fn git(repo: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(repo)
+        .status()
+        .context("Failed running git")?;
+    if !status.success() {
+        return Err(anyhow!("git {} failed with {status}", args.join(" ")));
+    }
+    Ok(())
+}
+
+/// Checks out `rev` of nixpkgs into `repo`, fetching only that single commit
+pub fn checkout_nixpkgs(repo: &Path, rev: &str) -> Result<()> {
+    create_dir_all(repo)?;
+    if !repo.join(".git").exists() {
+        git(repo, &["init", "--quiet"])?;
+        git(repo, &["remote", "add", "origin", NIXPKGS_URL])?;
+    } else {
+        git(repo, &["remote", "set-url", "origin", NIXPKGS_URL])?;
+    }
+    log::info!("Fetching nixpkgs revision {rev} into {}", repo.display());
+    git(repo, &["fetch", "--quiet", "--depth", "1", "origin", rev])?;
+    git(repo, &["reset", "--quiet", "--hard", "FETCH_HEAD"])?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Find the maintainers of all failed builds of evaluations
+
+mod checkout;
+mod nix;
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::Write as _;
+use std::path::Path;
+use zhf_core::{parse_lines, Build, Eval, MaintainedBuild, Maintainer, Store};
+
+/// Finds the maintainers of all failed builds of the given evaluations, given together with
+/// their nixpkgs revision and whether they are NixOS evaluations.
+///
+/// Writes `data_dir/maintainerscache/{eval_id}.cache` and records the maintainers in the store.
+/// Jobs whose maintainers could not be evaluated are cached with an unknown maintainer and listed
+/// with the reason in `data_dir/maintainerscache/{eval_id}.errors`.
+pub fn fetch_maintainers(
+    data_dir: &Path,
+    store: &Store,
+    argv: &[(u64, String, bool)],
+) -> Result<()> {
+    log::info!(
+        "Will fetch maintainers of evaluations: {:?}",
+        argv.iter().map(|(e, _, _)| e).collect::<Vec<_>>()
+    );
+
+    // Prepare directories
+    let mut maintainers_cache = data_dir.to_path_buf();
+    maintainers_cache.push("maintainerscache");
+    create_dir_all(&maintainers_cache)?;
+    let mut nixpkgs_dir = data_dir.to_path_buf();
+    nixpkgs_dir.push("nixpkgs");
+    let mut expr_file = data_dir.to_path_buf();
+    expr_file.push("maintainers.nix");
+
+    for (eval_id, rev, eval_nixos) in argv {
+        let (eval_id, eval_nixos) = (*eval_id, *eval_nixos);
+        let mut cache_file = maintainers_cache.clone();
+        cache_file.push(format!("{eval_id}.cache"));
+        if cache_file.exists() {
+            log::info!("Maintainers of evaluation {eval_id} are already cached");
+            if store.maintainers(eval_id)?.is_empty() {
+                let contents = read_to_string(&cache_file)?;
+                store.record_maintainers(eval_id, &parse_lines(&contents)?)?;
+            }
+            continue;
+        }
+
+        // Failed builds, named like in the NixOS jobset
+        let mut eval_loc = data_dir.to_path_buf();
+        eval_loc.push("evalcache");
+        eval_loc.push(format!("{eval_id}.cache"));
+        let builds: Vec<Build> = Eval::read_cache(eval_id, &eval_loc)?
+            .builds
+            .into_iter()
+            .filter(|build| build.status.is_failure())
+            .map(|mut build| {
+                if !eval_nixos {
+                    build.attr = format!("nixpkgs.{}", build.attr);
+                }
+                build
+            })
+            .collect();
+
+        // The maintainers don't differ between systems so only evaluate each job once
+        let mut jobs = BTreeMap::new();
+        for build in &builds {
+            jobs.entry(without_system(&build.attr).to_string())
+                .or_insert_with(|| build.attr.clone());
+        }
+
+        checkout::checkout_nixpkgs(&nixpkgs_dir, rev)?;
+        let maintainers = nix::evaluate_maintainers(&expr_file, &jobs)?;
+
+        let mut lines = vec![];
+        let mut errors = vec![];
+        for build in builds {
+            let key = without_system(&build.attr);
+            let error = match maintainers.get(key) {
+                Some(Ok(found)) if found.is_empty() => {
+                    lines.push(MaintainedBuild {
+                        maintainer: Maintainer::Nobody,
+                        build,
+                    });
+                    continue;
+                }
+                Some(Ok(found)) => {
+                    for maintainer in found {
+                        lines.push(MaintainedBuild {
+                            maintainer: Maintainer::Handle(maintainer.clone()),
+                            build: build.clone(),
+                        });
+                    }
+                    continue;
+                }
+                Some(Err(e)) => e.as_str(),
+                None => "not evaluated",
+            };
+            errors.push(format!("{}: {error}", build.attr));
+            lines.push(MaintainedBuild {
+                maintainer: Maintainer::Unknown,
+                build,
+            });
+        }
+
+        if !errors.is_empty() {
+            log::warn!(
+                "Could not evaluate the maintainers of {} jobs of evaluation {eval_id}",
+                errors.len()
+            );
+            errors.sort();
+            let mut errors_file = maintainers_cache.clone();
+            errors_file.push(format!("{eval_id}.errors"));
+            let mut out = File::create(errors_file)?;
+            for error in &errors {
+                log::warn!("{error}");
+                out.write_fmt(format_args!("{error}\n"))?;
+            }
+        }
+
+        let mut out = File::create(cache_file)?;
+        for line in &lines {
+            out.write_fmt(format_args!("{line}\n"))?;
+        }
+        store.record_maintainers(eval_id, &lines)?;
+    }
+    Ok(())
+}
+
+/// Strips the system from the name of a job
+fn without_system(attr: &str) -> &str {
+    attr.rsplit_once('.').map(|(name, _)| name).unwrap_or(attr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_the_system_of_jobs() {
+        assert_eq!(
+            without_system("nixpkgs.hello.x86_64-linux"),
+            "nixpkgs.hello"
+        );
+        assert_eq!(without_system("tested"), "tested");
+    }
+}
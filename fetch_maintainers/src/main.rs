@@ -0,0 +1,28 @@
+//! Find the maintainers of all failed builds of evaluations
+//!
+//! Takes triples of `eval_id nixpkgs_revision jobset`, where `jobset` is the name of a jobset of
+//! the configured branch.
+
+use anyhow::{anyhow, Result};
+
+fn main() -> Result<()> {
+    env_logger::builder().format_timestamp(None).init();
+    let branch = zhf_core::Config::load()?.branch(None)?.clone();
+
+    // Handle args
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.len().is_multiple_of(3) {
+        return Err(anyhow!(
+            "Expected triples of evaluation, nixpkgs revision and jobset"
+        ));
+    }
+    let mut argv: Vec<(u64, String, bool)> = Vec::new();
+    for arg in args.chunks(3) {
+        argv.push((arg[0].parse()?, arg[1].clone(), branch.jobset(&arg[2])?.is_nixos()));
+    }
+
+    let mut data_dir = std::env::current_dir()?;
+    data_dir.push("data");
+    let store = zhf_core::Store::open_in(&data_dir)?;
+    fetch_maintainers::fetch_maintainers(&data_dir, &store, &argv)
+}
@@ -0,0 +1,223 @@
+//! Evaluates the maintainers of many Hydra jobs with a single Nix invocation
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+/// Helpers shared by all jobs. `releases` are imported lazily, so only the release files that
+/// actually contain jobs get evaluated. Jobs are looked up in the plain release files instead of
+/// `release-combined.nix`, which strips the maintainers of all jobs it aggregates.
+const PRELUDE: &str = r#"let
+  lib = import ./nixpkgs/lib;
+  releases = {
+    nixpkgs = import ./nixpkgs/pkgs/top-level/release.nix { };
+    nixos = import ./nixpkgs/nixos/release.nix { };
+    combined = import ./nixpkgs/nixos/release-combined.nix { };
+  };
+  maintainersOf = release: path:
+    let
+      job = builtins.tryEval (lib.attrByPath path null release);
+      maintainers = map (m: m.github or null) (job.value.meta.maintainers or [ ]);
+      result = builtins.tryEval (builtins.deepSeq maintainers maintainers);
+    in
+    if !job.success then { error = "failed to evaluate the job"; }
+    else if job.value == null then { error = "job does not exist"; }
+    else if !result.success then { error = "failed to evaluate meta.maintainers"; }
+    else { maintainers = result.value; };
+in
+"#;
+
+/// The maintainers of each key, or the reason why they couldn't be evaluated
+type Maintainers = HashMap<String, Result<Vec<String>, String>>;
+
+#[derive(Deserialize)]
+struct Outcome {
+    maintainers: Option<Vec<Option<String>>>,
+    error: Option<String>,
+}
+
+/// Quotes a string for use in a Nix expression
+fn nix_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("${", "\\${");
+    format!("\"{escaped}\"")
+}
+
+/// Finds the release file and attribute path of a job of the `nixos:trunk-combined` jobset
+fn job_location(job: &str) -> (&'static str, Vec<&str>) {
+    let parts: Vec<&str> = job.split('.').collect();
+    match parts[0] {
+        "nixpkgs" => ("nixpkgs", parts[1..].to_vec()),
+        "nixos" => ("nixos", parts[1..].to_vec()),
+        _ => ("combined", parts),
+    }
+}
+
+/// Builds the expression evaluating the maintainers of all `jobs`, keyed by the given names
+fn expression(jobs: &BTreeMap<String, String>) -> String {
+    let mut expr = PRELUDE.to_string();
+    expr.push_str("{\n");
+    for (key, job) in jobs {
+        let (release, path) = job_location(job);
+        let path: Vec<String> = path.into_iter().map(nix_string).collect();
+        writeln!(
+            expr,
+            "  {} = maintainersOf releases.{release} [ {} ];",
+            nix_string(key),
+            path.join(" ")
+        )
+        .unwrap();
+    }
+    expr.push_str("}\n");
+    expr
+}
+
+/// Evaluates the GitHub handles of the maintainers of all `jobs` (key to job name) in the
+/// nixpkgs checkout next to `expr_file`.
+///
+/// Jobs are named like in `nixos:trunk-combined`, i.e. `nixpkgs.hello.x86_64-linux`. Returns the
+/// maintainers or the reason why they couldn't be evaluated for every key. Not every evaluation
+/// error can be caught in Nix, so if evaluating a batch of jobs fails, it's split in halves until
+/// the jobs that fail are found.
+pub fn evaluate_maintainers(
+    expr_file: &Path,
+    jobs: &BTreeMap<String, String>,
+) -> Result<Maintainers> {
+    log::info!("Evaluating maintainers of {} jobs", jobs.len());
+    let mut result = HashMap::new();
+    let mut batches = vec![jobs.clone()];
+    while let Some(mut batch) = batches.pop() {
+        let error = match instantiate(expr_file, &batch)? {
+            Ok(outcomes) => {
+                result.extend(outcomes);
+                continue;
+            }
+            Err(error) => error,
+        };
+        if batch.len() == 1 {
+            let (key, job) = batch.pop_first().unwrap();
+            log::warn!("Evaluating the maintainers of {job} failed: {error}");
+            result.insert(key, Err(error));
+            continue;
+        }
+        log::warn!(
+            "Evaluating the maintainers of {} jobs failed, splitting them up",
+            batch.len()
+        );
+        let middle = batch.keys().nth(batch.len() / 2).unwrap().clone();
+        let second = batch.split_off(&middle);
+        batches.push(batch);
+        batches.push(second);
+    }
+    Ok(result)
+}
+
+/// Evaluates the maintainers of a batch of jobs. Returns the last error of Nix if the batch
+/// couldn't be evaluated.
+fn instantiate(
+    expr_file: &Path,
+    jobs: &BTreeMap<String, String>,
+) -> Result<Result<Maintainers, String>> {
+    std::fs::write(expr_file, expression(jobs))
+        .with_context(|| format!("Failed writing {}", expr_file.display()))?;
+    let output = Command::new("nix-instantiate")
+        .args(["--eval", "--strict", "--json"])
+        .arg(expr_file)
+        .output()
+        .context("Failed running nix-instantiate")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Ok(Err(last_error(&stderr).unwrap_or_else(|| {
+            format!("nix-instantiate failed with {}", output.status)
+        })));
+    }
+    let outcomes: HashMap<String, Outcome> =
+        serde_json::from_slice(&output.stdout).context("Invalid output of nix-instantiate")?;
+
+    let mut result = HashMap::new();
+    for (key, outcome) in outcomes {
+        let maintainers = match outcome {
+            Outcome {
+                maintainers: Some(maintainers),
+                ..
+            } => {
+                if maintainers.iter().any(Option::is_none) {
+                    log::warn!("{key} has maintainers without a GitHub handle");
+                }
+                Ok(maintainers.into_iter().flatten().collect())
+            }
+            Outcome {
+                error: Some(error), ..
+            } => Err(error),
+            _ => Err("no result".to_string()),
+        };
+        result.insert(key, maintainers);
+    }
+    Ok(Ok(result))
+}
+
+/// The last line of the output of Nix that reports an error
+fn last_error(stderr: &str) -> Option<String> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .rfind(|line| line.starts_with("error:"))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_nix_strings() {
+        assert_eq!(nix_string("hello"), r#""hello""#);
+        assert_eq!(nix_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(nix_string("${x}"), r#""\${x}""#);
+    }
+
+    #[test]
+    fn finds_the_release_file_of_jobs() {
+        assert_eq!(
+            job_location("nixpkgs.hello.x86_64-linux"),
+            ("nixpkgs", vec!["hello", "x86_64-linux"])
+        );
+        assert_eq!(
+            job_location("nixos.tests.simple.x86_64-linux"),
+            ("nixos", vec!["tests", "simple", "x86_64-linux"])
+        );
+        assert_eq!(job_location("tested"), ("combined", vec!["tested"]));
+    }
+
+    #[test]
+    fn evaluates_all_jobs_in_one_expression() {
+        let jobs = BTreeMap::from([
+            (
+                "nixpkgs.hello".to_string(),
+                "nixpkgs.hello.x86_64-linux".to_string(),
+            ),
+            ("tested".to_string(), "tested".to_string()),
+        ]);
+        let expr = expression(&jobs);
+        assert!(expr.starts_with(PRELUDE));
+        assert!(expr.ends_with(
+            "{\n  \"nixpkgs.hello\" = maintainersOf releases.nixpkgs [ \"hello\" \"x86_64-linux\" ];\n  \
+             \"tested\" = maintainersOf releases.combined [ \"tested\" ];\n}\n"
+        ));
+    }
+
+    #[test]
+    fn finds_the_last_error_of_nix() {
+        let stderr = "warning: something\nerror:\n       … while evaluating\n\n       error: assertion failed\n";
+        assert_eq!(
+            last_error(stderr).as_deref(),
+            Some("error: assertion failed")
+        );
+        assert_eq!(last_error("warning: something\n"), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! The response cache (`data/httpcache/`)
+//!
+//! Every successful answer to a GET request is kept in a file named after the hash of its URL and
+//! `Accept` header. The file starts with a line of JSON describing the answer, followed by the
+//! body. Answers to requests marked [`Immutable`] are served from the cache without asking Hydra,
+//! all others are revalidated with their `ETag` and `Last-Modified` headers. When the cache grows
+//! above its limit, the least recently used answers are dropped.
+
+use crate::Metrics;
+use anyhow::{anyhow, Context, Result};
+use reqwest::header::{
+    HeaderValue, ACCEPT, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::{Method, Request, Response, ResponseBuilderExt, StatusCode, Url};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, read, read_dir, remove_file, rename, File};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use task_local_extensions::Extensions;
+
+/// Marks a request whose answer never changes once it was successful, like the page of a finished
+/// build. Set it with `RequestBuilder::with_extension`.
+#[derive(Debug, Clone, Copy)]
+pub struct Immutable;
+
+/// What is known about a cached answer
+#[derive(Debug, Serialize, Deserialize)]
+struct Meta {
+    /// The URL and `Accept` header the answer belongs to
+    key: String,
+    content_type: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    immutable: bool,
+}
+
+/// Middleware answering requests from the cache
+pub struct ResponseCache {
+    dir: PathBuf,
+    /// The size of the cache in bytes at most
+    limit: u64,
+    /// The size of the cache in bytes, as far as this process knows
+    size: Mutex<u64>,
+    /// Whether answers are only served from the cache
+    offline: bool,
+    metrics: Arc<Metrics>,
+}
+
+impl ResponseCache {
+    /// Opens the cache in `dir`, creating it if needed
+    pub fn open(dir: &Path, limit: u64, offline: bool, metrics: Arc<Metrics>) -> Result<Self> {
+        create_dir_all(dir)
+            .with_context(|| format!("Failed creating the cache {}", dir.display()))?;
+        let size = entries(dir)?.iter().map(|(_, _, len)| len).sum();
+        Ok(ResponseCache {
+            dir: dir.to_path_buf(),
+            limit,
+            size: Mutex::new(size),
+            offline,
+            metrics,
+        })
+    }
+
+    /// The file of a cache key
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}", fnv1a(key.as_bytes())))
+    }
+
+    /// Reads the cached answer of a key, if there is one
+    fn load(&self, key: &str) -> Option<(Meta, Vec<u8>)> {
+        let contents = read(self.path(key)).ok()?;
+        let newline = contents.iter().position(|&b| b == b'\n')?;
+        let meta: Meta = serde_json::from_slice(&contents[..newline]).ok()?;
+        // Different keys may have the same hash
+        if meta.key != key {
+            return None;
+        }
+        Some((meta, contents[newline + 1..].to_vec()))
+    }
+
+    /// Marks the answer of a key as recently used
+    fn touch(&self, key: &str) {
+        let touched = File::options()
+            .write(true)
+            .open(self.path(key))
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = touched {
+            log::debug!("Failed touching the cached answer of {key}: {e}");
+        }
+    }
+
+    /// Writes an answer to the cache, dropping old answers if it gets too large
+    fn store(&self, meta: &Meta, body: &[u8]) -> Result<()> {
+        static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+        let path = self.path(&meta.key);
+        let temp = self.dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        {
+            let mut file = File::create(&temp)?;
+            serde_json::to_writer(&mut file, meta)?;
+            file.write_all(b"\n")?;
+            file.write_all(body)?;
+        }
+        let mut size = self.size.lock().unwrap();
+        let old_len = path.metadata().map(|m| m.len()).unwrap_or(0);
+        let len = temp.metadata()?.len();
+        rename(&temp, &path)?;
+        *size = (*size + len).saturating_sub(old_len);
+        if *size > self.limit {
+            *size = self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// Drops the least recently used answers until the cache is below 90% of its limit, returning
+    /// its new size
+    fn evict(&self) -> Result<u64> {
+        let mut entries = entries(&self.dir)?;
+        let mut size: u64 = entries.iter().map(|(_, _, len)| len).sum();
+        entries.sort_by_key(|(_, modified, _)| *modified);
+        let mut dropped = 0;
+        for (path, _, len) in entries {
+            if size <= self.limit / 10 * 9 {
+                break;
+            }
+            remove_file(&path)?;
+            size -= len;
+            dropped += 1;
+        }
+        log::info!(
+            "Dropped {dropped} answers from the cache {}",
+            self.dir.display()
+        );
+        Ok(size)
+    }
+}
+
+/// All answers in the cache directory with their modification time and size
+fn entries(dir: &Path) -> Result<Vec<(PathBuf, SystemTime, u64)>> {
+    let mut entries = vec![];
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        // Skip files that are still being written
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        entries.push((entry.path(), metadata.modified()?, metadata.len()));
+    }
+    Ok(entries)
+}
+
+/// A hash that stays the same across Rust versions, so cache files are found again
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Builds the answer to a request from the cache
+fn respond(url: &Url, meta: &Meta, body: Vec<u8>) -> Response {
+    let mut builder = http::Response::builder()
+        .status(StatusCode::OK)
+        .url(url.clone());
+    if let Some(content_type) = &meta.content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+    Response::from(
+        builder
+            .body(body)
+            .expect("A cached answer is a valid response"),
+    )
+}
+
+/// The value of a header, if it's a string
+fn header(res: &Response, name: reqwest::header::HeaderName) -> Option<String> {
+    res.headers().get(name)?.to_str().ok().map(str::to_string)
+}
+
+#[async_trait::async_trait]
+impl Middleware for ResponseCache {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if req.method() != Method::GET {
+            return next.run(req, extensions).await;
+        }
+        let url = req.url().clone();
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("*/*");
+        let key = format!("{url} {accept}");
+        let immutable = extensions.get::<Immutable>().is_some();
+        let cached = self.load(&key);
+
+        if self.offline {
+            let (meta, body) = cached.ok_or_else(|| {
+                reqwest_middleware::Error::Middleware(anyhow!(
+                    "{url} is not cached, and requests are disabled by --offline"
+                ))
+            })?;
+            self.metrics.cached.fetch_add(1, Ordering::Relaxed);
+            return Ok(respond(&url, &meta, body));
+        }
+        if let Some((meta, body)) = cached {
+            if meta.immutable {
+                self.touch(&key);
+                self.metrics.cached.fetch_add(1, Ordering::Relaxed);
+                return Ok(respond(&url, &meta, body));
+            }
+            // Ask Hydra whether the cached answer is still current
+            let headers = req.headers_mut();
+            for (name, value) in [
+                (IF_NONE_MATCH, &meta.etag),
+                (IF_MODIFIED_SINCE, &meta.last_modified),
+            ] {
+                if let Some(value) = value.as_ref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                    headers.insert(name, value);
+                }
+            }
+            let res = next.run(req, extensions).await?;
+            if res.status() == StatusCode::NOT_MODIFIED {
+                self.touch(&key);
+                self.metrics.cached.fetch_add(1, Ordering::Relaxed);
+                return Ok(respond(&url, &meta, body));
+            }
+            return self.keep(key, immutable, res).await;
+        }
+
+        let res = next.run(req, extensions).await?;
+        self.keep(key, immutable, res).await
+    }
+}
+
+impl ResponseCache {
+    /// Caches a successful answer and passes it on
+    async fn keep(
+        &self,
+        key: String,
+        immutable: bool,
+        res: Response,
+    ) -> reqwest_middleware::Result<Response> {
+        if res.status() != StatusCode::OK {
+            return Ok(res);
+        }
+        let url = res.url().clone();
+        let meta = Meta {
+            key,
+            content_type: header(&res, CONTENT_TYPE),
+            etag: header(&res, ETAG),
+            last_modified: header(&res, LAST_MODIFIED),
+            immutable,
+        };
+        let body = res.bytes().await?.to_vec();
+        if let Err(e) = self.store(&meta, &body) {
+            log::warn!("Failed caching {url}: {e}");
+        }
+        Ok(respond(&url, &meta, body))
+    }
+}
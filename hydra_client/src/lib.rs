@@ -0,0 +1,251 @@
+//! The HTTP client all tools crawl Hydra with
+//!
+//! Every request goes through a [`Scheduler`], which bounds how many requests are in flight,
+//! spaces them out to the configured rate and retries the ones that failed transiently. A
+//! `Retry-After` header of an answer pauses all requests of the client, not just the retried one.
+//! Before that, requests are answered from the response cache if possible.
+
+mod cache;
+
+pub use cache::{Immutable, ResponseCache};
+
+use anyhow::Result;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use task_local_extensions::Extensions;
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, sleep_until, Instant};
+use zhf_core::HttpConfig;
+
+/// Sent with every request so Hydra admins know who is crawling
+pub const USER_AGENT: &str = concat!(
+    "zhf/",
+    env!("CARGO_PKG_VERSION"),
+    " (Zero Hydra Failures tracker; +https://zh.fail)"
+);
+
+/// The delay before the first retry, doubled for every further one
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest delay between two retries, also the longest `Retry-After` that is honored
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// The directory of the response cache below `data/`, shared by all tools
+pub const CACHE_DIR: &str = "httpcache";
+
+/// Builds a client whose requests are scheduled by the given limits and whose answers are cached
+/// in `data_dir/httpcache`. If `offline` is set, all answers come from the cache and requests that
+/// are not cached fail.
+pub fn client(
+    config: &HttpConfig,
+    data_dir: &Path,
+    offline: bool,
+) -> Result<(ClientWithMiddleware, Arc<Metrics>)> {
+    let scheduler = Scheduler::new(config);
+    let metrics = scheduler.metrics.clone();
+    let cache = ResponseCache::open(
+        &data_dir.join(CACHE_DIR),
+        config.max_cache_size,
+        offline,
+        metrics.clone(),
+    )?;
+    let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+    Ok((
+        ClientBuilder::new(client)
+            .with(cache)
+            .with(scheduler)
+            .build(),
+        metrics,
+    ))
+}
+
+/// Removes `--offline` from the arguments of a tool, returning whether it was given
+pub fn take_offline_flag(args: &mut Vec<String>) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != "--offline");
+    args.len() != len
+}
+
+/// Counters of the requests sent by a client
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    cached: AtomicU64,
+    retries: AtomicU64,
+    throttled: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Metrics {
+    /// Requests sent, including retries
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Requests answered from the cache, including revalidated answers
+    pub fn cached(&self) -> u64 {
+        self.cached.load(Ordering::Relaxed)
+    }
+
+    /// Requests that were sent again
+    pub fn retries(&self) -> u64 {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Retries that waited for a `Retry-After` header
+    pub fn throttled(&self) -> u64 {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// Requests that still failed after all retries
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests, {} answered from the cache, {} retries ({} throttled by Hydra), {} failed",
+            self.requests(),
+            self.cached(),
+            self.retries(),
+            self.throttled(),
+            self.failed()
+        )
+    }
+}
+
+/// Middleware limiting and retrying the requests of a client
+pub struct Scheduler {
+    in_flight: Semaphore,
+    /// The time between two requests
+    interval: Duration,
+    /// When the next request may be started
+    next_slot: Mutex<Instant>,
+    max_retries: u32,
+    metrics: Arc<Metrics>,
+}
+
+impl Scheduler {
+    pub fn new(config: &HttpConfig) -> Self {
+        Scheduler {
+            in_flight: Semaphore::new(config.max_in_flight),
+            interval: Duration::from_secs_f64(1.0 / config.requests_per_second),
+            next_slot: Mutex::new(Instant::now()),
+            max_retries: config.max_retries,
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+
+    /// Waits until the rate limit allows another request
+    async fn wait_for_slot(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+
+    /// Holds back all requests for the given time
+    fn pause(&self, delay: Duration) {
+        let mut next_slot = self.next_slot.lock().unwrap();
+        *next_slot = (*next_slot).max(Instant::now() + delay);
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Scheduler {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let mut retries = 0;
+        loop {
+            // Requests with a streamed body can't be retried
+            let Some(attempt) = req.try_clone() else {
+                let _permit = self.in_flight.acquire().await.unwrap();
+                self.wait_for_slot().await;
+                self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+                return next.run(req, extensions).await;
+            };
+
+            let result = {
+                let _permit = self.in_flight.acquire().await.unwrap();
+                self.wait_for_slot().await;
+                self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+                next.clone().run(attempt, extensions).await
+            };
+
+            let backoff = MIN_BACKOFF
+                .saturating_mul(1 << retries.min(16))
+                .min(MAX_BACKOFF);
+            let (reason, retry_after) = match &result {
+                Ok(res) if is_transient(res.status()) => {
+                    (res.status().to_string(), retry_after(res))
+                }
+                Err(reqwest_middleware::Error::Reqwest(e))
+                    if e.is_timeout() || e.is_connect() || e.is_request() =>
+                {
+                    (e.to_string(), None)
+                }
+                _ => return result,
+            };
+            if retries >= self.max_retries {
+                log::warn!(
+                    "Giving up on {} after {retries} retries: {reason}",
+                    req.url()
+                );
+                self.metrics.failed.fetch_add(1, Ordering::Relaxed);
+                return result;
+            }
+            retries += 1;
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+            match retry_after {
+                Some(delay) => {
+                    log::warn!("Hydra asked to retry {} in {delay:?}: {reason}", req.url());
+                    self.metrics.throttled.fetch_add(1, Ordering::Relaxed);
+                    self.pause(delay.min(MAX_BACKOFF));
+                }
+                None => {
+                    log::warn!("Retrying {} in {backoff:?}: {reason}", req.url());
+                    sleep(backoff).await;
+                }
+            }
+        }
+    }
+}
+
+/// Whether a request answered with this status may succeed when it's sent again
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+/// The delay requested by the `Retry-After` header, given in seconds or as a date
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?;
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
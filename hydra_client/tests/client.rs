@@ -0,0 +1,196 @@
+//! Send requests through the scheduler and the response cache
+
+use hydra_fixtures::{pages_dir, FixtureServer};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use zhf_core::HttpConfig;
+
+/// Answers one request after another with the given raw HTTP answers. Sends the request line and
+/// headers of every request.
+fn scripted_server(answers: &'static [&'static str]) -> (String, mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for answer in answers {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                request.push(line);
+            }
+            stream.write_all(answer.as_bytes()).unwrap();
+            tx.send(request).unwrap();
+        }
+    });
+    (url, rx)
+}
+
+/// Fetches the body of a URL
+async fn get(client: &reqwest_middleware::ClientWithMiddleware, url: &str) -> String {
+    client
+        .get(url)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+/// Whether a request has the given header
+fn has_header(request: &[String], header: &str) -> bool {
+    request.iter().any(|line| line.eq_ignore_ascii_case(header))
+}
+
+#[tokio::test]
+async fn honors_retry_after() {
+    let (url, requests) = scripted_server(&[
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+    ]);
+    let data_dir = tempfile::tempdir().unwrap();
+    let (client, metrics) =
+        hydra_client::client(&HttpConfig::default(), data_dir.path(), false).unwrap();
+
+    let start = Instant::now();
+    assert_eq!(get(&client, &format!("{url}/build/1")).await, "ok");
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(metrics.requests(), 2);
+    assert_eq!(metrics.retries(), 1);
+    assert_eq!(metrics.throttled(), 1);
+    assert_eq!(metrics.failed(), 0);
+
+    let user_agent = format!("user-agent: {}", hydra_client::USER_AGENT);
+    for request in requests.iter().take(2) {
+        assert!(has_header(&request, &user_agent));
+    }
+}
+
+#[tokio::test]
+async fn limits_the_request_rate() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let config = HttpConfig {
+        requests_per_second: 10.0,
+        ..HttpConfig::default()
+    };
+    let (client, metrics) = hydra_client::client(&config, data_dir.path(), false).unwrap();
+
+    let start = Instant::now();
+    let requests = (0..5).map(|_| client.get(format!("{}/build/103", server.url())).send());
+    for res in futures::future::join_all(requests).await {
+        assert!(res.unwrap().status().is_success());
+    }
+    // The first request starts right away, the others 100ms apart
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    assert_eq!(metrics.requests(), 5);
+    assert_eq!(metrics.retries(), 0);
+}
+
+#[tokio::test]
+async fn serves_immutable_answers_from_the_cache() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/build/103", server.url());
+    let fetch = |client: reqwest_middleware::ClientWithMiddleware, url: String| async move {
+        client
+            .get(url)
+            .with_extension(hydra_client::Immutable)
+            .send()
+            .await
+            .and_then(|res| Ok(res.error_for_status()?))
+    };
+
+    let (client, metrics) =
+        hydra_client::client(&HttpConfig::default(), data_dir.path(), false).unwrap();
+    let body = fetch(client.clone(), url.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let cached = fetch(client, url.clone()).await.unwrap();
+    assert_eq!(
+        cached.headers()["content-type"].to_str().unwrap(),
+        "text/html"
+    );
+    assert_eq!(cached.text().await.unwrap(), body);
+    assert_eq!(metrics.requests(), 1);
+    assert_eq!(metrics.cached(), 1);
+
+    // Other tools share the cache, even without network access
+    drop(server);
+    let (client, metrics) =
+        hydra_client::client(&HttpConfig::default(), data_dir.path(), true).unwrap();
+    let offline = fetch(client.clone(), url.clone()).await.unwrap();
+    assert_eq!(offline.text().await.unwrap(), body);
+    let missing = fetch(client, url.replace("103", "105")).await.unwrap_err();
+    assert!(missing.to_string().contains("is not cached"));
+    assert_eq!(metrics.requests(), 0);
+}
+
+#[tokio::test]
+async fn revalidates_cached_answers() {
+    let (url, requests) = scripted_server(&[
+        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 3\r\nConnection: close\r\n\r\none",
+        "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 3\r\nConnection: close\r\n\r\ntwo",
+    ]);
+    let data_dir = tempfile::tempdir().unwrap();
+    let (client, metrics) =
+        hydra_client::client(&HttpConfig::default(), data_dir.path(), false).unwrap();
+    let url = format!("{url}/eval/1");
+
+    assert_eq!(get(&client, &url).await, "one");
+    assert_eq!(get(&client, &url).await, "one");
+    assert_eq!(get(&client, &url).await, "two");
+    assert_eq!(metrics.requests(), 3);
+    assert_eq!(metrics.cached(), 1);
+
+    let requests: Vec<Vec<String>> = requests.iter().take(3).collect();
+    assert!(!requests[0]
+        .iter()
+        .any(|line| line.starts_with("if-none-match")));
+    assert!(has_header(&requests[1], "if-none-match: \"v1\""));
+    assert!(has_header(&requests[2], "if-none-match: \"v1\""));
+}
+
+/// The number of answers in the cache
+fn cache_entries(data_dir: &Path) -> usize {
+    std::fs::read_dir(data_dir.join(hydra_client::CACHE_DIR))
+        .unwrap()
+        .count()
+}
+
+#[tokio::test]
+async fn drops_the_least_recently_used_answers() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let data_dir = tempfile::tempdir().unwrap();
+    // Room for about two build pages
+    let config = HttpConfig {
+        max_cache_size: 4000,
+        ..HttpConfig::default()
+    };
+    let (client, _) = hydra_client::client(&config, data_dir.path(), false).unwrap();
+
+    let url = server.url().to_string();
+    for build in [102, 103, 105, 204] {
+        get(&client, &format!("{url}/build/{build}")).await;
+        assert!(cache_entries(data_dir.path()) <= 2);
+    }
+    drop(server);
+
+    // The newest page survived, the oldest one didn't
+    let (client, _) = hydra_client::client(&config, data_dir.path(), true).unwrap();
+    get(&client, &format!("{url}/build/204")).await;
+    assert!(client.get(format!("{url}/build/102")).send().await.is_err());
+}
@@ -0,0 +1,137 @@
+//! A tiny HTTP server that plays back recorded Hydra pages so the crawlers can run offline.
+//!
+//! A request for `/some/path?query` is answered with the file `some/path@query.html` below the
+//! fixture directory, or `some/path@query.json` if the request asks for
+//! `Accept: application/json`. Requests without a query string map to `some/path.html` and
+//! `some/path.json`. Unknown paths get a 404.
+//!
+//! New fixtures can be recorded with curl, e.g.
+//! `curl -H 'Accept: application/json' https://hydra.nixos.org/build/1 > pages/build/1.json`.
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// The recorded pages shipped with this crate
+pub fn pages_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("pages")
+}
+
+/// A running fixture server
+pub struct FixtureServer {
+    url: String,
+}
+
+impl FixtureServer {
+    /// Starts serving `root` on a random local port in a background thread
+    pub fn start(root: impl Into<PathBuf>) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let root = root.into();
+        thread::spawn(move || serve(&listener, &root));
+        Ok(FixtureServer { url })
+    }
+
+    /// The base URL to use as `HYDRA_URL`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+/// Accepts connections on `listener` forever
+pub fn serve(listener: &TcpListener, root: &Path) {
+    for stream in listener.incoming() {
+        let root = root.to_path_buf();
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(e) = handle(stream, &root) {
+                        log::error!("Failed handling request: {e}");
+                    }
+                });
+            }
+            Err(e) => log::error!("Failed accepting connection: {e}"),
+        }
+    }
+}
+
+/// Answers a single request
+fn handle(mut stream: TcpStream, root: &Path) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let target = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+    // Read headers until the empty line
+    let mut json = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("accept") && value.contains("application/json") {
+                json = true;
+            }
+        }
+    }
+
+    let file = fixture_path(root, &target, json);
+    let (status, content_type, body) = match std::fs::read(&file) {
+        Ok(body) => {
+            let content_type = if json { "application/json" } else { "text/html" };
+            ("200 OK", content_type, body)
+        }
+        Err(_) => {
+            log::warn!("No fixture for {target} at {}", file.display());
+            ("404 Not Found", "text/plain", b"Not found".to_vec())
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+/// Maps a request target to the file holding the recorded response
+fn fixture_path(root: &Path, target: &str, json: bool) -> PathBuf {
+    let target = target.trim_start_matches('/');
+    let name = match target.split_once('?') {
+        Some((path, query)) => format!("{path}@{query}"),
+        None => target.to_string(),
+    };
+    let extension = if json { "json" } else { "html" };
+    root.join(format!("{name}.{extension}"))
+}
+
+/// Asserts that every file below `golden` exists below `actual` with the same contents.
+///
+/// Setting `UPDATE_GOLDEN=1` copies the actual files over the golden ones instead.
+pub fn assert_golden_dir(actual: &Path, golden: &Path) {
+    for entry in std::fs::read_dir(golden).expect("golden directory is missing") {
+        let golden_file = entry.unwrap().path();
+        let actual_file = actual.join(golden_file.file_name().unwrap());
+        if golden_file.is_dir() {
+            assert_golden_dir(&actual_file, &golden_file);
+            continue;
+        }
+        let actual_contents = std::fs::read_to_string(&actual_file)
+            .unwrap_or_else(|_| panic!("{} was not written", actual_file.display()));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden_file, actual_contents).unwrap();
+            continue;
+        }
+        let golden_contents = std::fs::read_to_string(&golden_file).unwrap();
+        assert_eq!(
+            actual_contents,
+            golden_contents,
+            "{} differs from {}",
+            actual_file.display(),
+            golden_file.display()
+        );
+    }
+}
@@ -0,0 +1,25 @@
+//! Serve recorded Hydra pages locally, e.g. to run the crawlers by hand with `HYDRA_URL` set
+
+use anyhow::Result;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+fn main() -> Result<()> {
+    env_logger::builder().format_timestamp(None).init();
+    // Handle args
+    let argv: Vec<String> = std::env::args().collect();
+    let root = argv
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(hydra_fixtures::pages_dir);
+    let port = argv.get(2).map(String::as_str).unwrap_or("8080");
+
+    let listener = TcpListener::bind(format!("127.0.0.1:{port}"))?;
+    log::info!(
+        "Serving {} on http://{}",
+        root.display(),
+        listener.local_addr()?
+    );
+    hydra_fixtures::serve(&listener, &root);
+    Ok(())
+}
@@ -0,0 +1,436 @@
+//! Renders the per-maintainer pages and overviews
+
+use anyhow::Result;
+use diff_evals::FlakyAttr;
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::Write as _;
+use std::path::Path;
+use zhf_core::{
+    dedup_builds, escape_html, parse_lines, Build, ClassifiedLog, Eval, MaintainedBuild, Store,
+    StoredEval, UniqueBuild,
+};
+
+/// Lets the reader hide builds that are failing for less than some days, see `page/js/tables.js`
+const MIN_DAYS_FILTER: &str = r#"<p><label>Only show builds failing for more than <input type="number" id="min-days" min="0" value="0"> days</label></p>"#;
+
+/// Renders `public_dir/failed/` from the maintainers caches of the given evaluations. The store
+/// tells since when each build is failing, what went wrong in the direct failures and which
+/// attributes are probably flaky.
+pub fn render_maintainer_pages(
+    hydra_url: &str,
+    site_url: &str,
+    data_dir: &Path,
+    store: &Store,
+    public_dir: &Path,
+    argv: &[u64],
+) -> Result<()> {
+    log::info!("Will generate evaluations: {:?}", argv);
+
+    // Prepare directories
+    let mut maintainers_cache = data_dir.to_path_buf();
+    maintainers_cache.push("maintainerscache");
+    let mut out_dir = public_dir.to_path_buf();
+    out_dir.push("failed");
+    out_dir.push("by-maintainer");
+    create_dir_all(&out_dir)?;
+
+    // Read the cache
+    let mut maintainers: HashMap<String, Vec<MaintainedBuild>> = HashMap::new();
+    let mut failing_since: HashMap<String, StoredEval> = HashMap::new();
+    let mut logs: HashMap<u64, ClassifiedLog> = HashMap::new();
+    let mut flaky: Vec<FlakyAttr> = vec![];
+    let mut evals: Vec<Eval> = vec![];
+    let mut jobsets: HashMap<u64, String> = HashMap::new();
+    for eval in argv {
+        logs.extend(store.classified_logs(*eval)?);
+        flaky.extend(diff_evals::flaky_attrs(store, *eval)?);
+        evals.extend(store.eval(*eval)?);
+        let since = match store.stored_eval(*eval)? {
+            Some(eval) => {
+                jobsets.insert(eval.id, eval.jobset.clone());
+                store.failing_since(&eval)?
+            }
+            None => HashMap::new(),
+        };
+        // Read maintainers cache
+        let mut cache_loc = maintainers_cache.clone();
+        cache_loc.push(format!("{eval}.cache"));
+        let builds: Vec<MaintainedBuild> = parse_lines(&read_to_string(cache_loc)?)?;
+        for build in builds {
+            // Attributes of nixpkgs evaluations are prefixed in the maintainers cache
+            let attr = &build.build.attr;
+            let eval_attr = attr.strip_prefix("nixpkgs.").unwrap_or(attr);
+            if let Some(since) = since.get(attr).or_else(|| since.get(eval_attr)) {
+                failing_since.insert(attr.clone(), since.clone());
+            }
+            // Group by maintainer
+            let maintainer = build.maintainer.to_string();
+            maintainers.entry(maintainer).or_default().push(build);
+        }
+    }
+
+    flaky.sort_by(|a, b| a.attr.cmp(&b.attr));
+    let flaky_builds: HashMap<u64, &FlakyAttr> =
+        flaky.iter().map(|attr| (attr.build.id, attr)).collect();
+
+    // Sort builds
+    for builds in maintainers.values_mut() {
+        builds.sort_by(|a, b| a.build.attr.cmp(&b.build.attr));
+    }
+    // Filter out successful builds
+    for builds in maintainers.values_mut() {
+        builds.retain(|x| x.build.status.is_failure());
+    }
+    // Filter out maintainers without failures
+    maintainers.retain(|_, x| !x.is_empty());
+
+    // Builds of several jobsets with the same derivation are listed once in all.html
+    let unique = dedup_builds(&evals);
+    let mut unique_of_attr: HashMap<(u64, &str), &UniqueBuild> = HashMap::new();
+    for build in &unique {
+        for (eval_id, attr) in &build.references {
+            unique_of_attr.insert((*eval_id, attr), build);
+        }
+    }
+    let unique_of: HashMap<u64, &UniqueBuild> = evals
+        .iter()
+        .flat_map(|eval| eval.builds.iter().map(move |build| (eval.id, build)))
+        .filter_map(|(eval_id, build)| {
+            let unique = unique_of_attr.get(&(eval_id, build.attr.as_str()))?;
+            Some((build.id, *unique))
+        })
+        .collect();
+
+    // For all.html
+    let mut all_failed_builds = HashMap::new();
+
+    // Render per-maintainer pages
+    for (maintainer_name, builds) in &maintainers {
+        // Pretty name for titles
+        let pretty_name = match maintainer_name.as_str() {
+            "_" => "nobody".to_string(),
+            "!" => "unknown maintainers".to_string(),
+            _ => maintainer_name.clone(),
+        };
+
+        let mut out = out_dir.clone();
+        out.push(format!("{maintainer_name}.html"));
+        let mut out = File::create(out)?;
+        // Write top part
+        out.write_fmt(format_args!(r#"<!DOCTYPE html>
+        <html lang="en">
+          <head>
+            <meta charset="UTF-8">
+            <meta name="viewport" content="width=device-width, initial-scale=1.0">
+            <meta http-equiv="X-UA-Compatible" content="ie=edge">
+            <title>Hydra failures ({pretty_name})</title>
+            <link rel="stylesheet" href="../../style.css">
+            <link rel="icon" type="image/x-icon" href="../../favicon.ico">
+            <meta property="og:title" content="Per-maintainer Hydra failures" />
+            <meta property="og:description" content="Track Hydra failures that have {pretty_name} as their maintainer" />
+            <meta property="og:type" content="website" />
+            <meta property="og:url" content="{site_url}/failed/by-maintainer/{maintainer_name}.html" />
+            <meta property="og:image" content="../../icon.png" />
+          </head>
+          <body id="maintainer-body">
+            <h1><a href="../../index.html" title="Go Home"><img src="../../nix-snowflake.svg"></a>Hydra failures for packages maintained by {pretty_name}</h1>
+            <p>Jump to: <a href='#direct'>Direct Failures</a>&nbsp;&bull;&nbsp;<a href='#indirect'>Indirect Failures</a></p>
+            {MIN_DAYS_FILTER}
+            <h2 id="direct">Direct failures</h2>
+            <p>These are packages fail to build themselves.</p>
+            <table class="sortable">
+              <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Result</th><th>Failing since</th><th>Log</th></tr></thead>
+              <tbody>"#))?;
+        // Table for direct failures
+        let mut found = false;
+        for build in builds {
+            // Propagate list for all.html
+            all_failed_builds.insert(build.build.attr.clone(), build);
+
+            if build.build.status.is_dependency_failure() {
+                continue;
+            }
+            found = true;
+            let since = since_cell(hydra_url, failing_since.get(&build.build.attr));
+            let build = &build.build;
+            let log = log_cell(logs.get(&build.id));
+            let badge = flaky_badge(flaky_builds.get(&build.id));
+            out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}{badge}</td>{since}{log}</tr>", build.id, escape_html(&build.attr), escape_html(&build.name), build.system, escape_html(&build.status.to_string())))?;
+        }
+        if !found {
+            out.write_fmt(format_args!(r#"<tr><td colspan="6" class="none">None 🎉</td></tr>"#))?;
+        }
+        // Middle between the two tables
+        out.write_fmt(format_args!(r#"</tbody>
+        </table>
+        <p>Jump to: <a href='#direct'>Direct Failures</a>&nbsp;&bull;&nbsp;<a href='#indirect'>Indirect Failures</a></p>
+        <h2 id="indirect">Indirect failures</h2>
+        <p>These are packages where a dependency failed to build.<br></p>
+        <table class="sortable">
+          <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Result</th><th>Failing since</th></tr></thead>
+          <tbody>"#))?;
+        // Table for indirect failures
+        let mut found = false;
+        for build in builds {
+            if !build.build.status.is_dependency_failure() {
+                continue;
+            }
+            found = true;
+            let since = since_cell(hydra_url, failing_since.get(&build.build.attr));
+            let build = &build.build;
+            out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td>{since}</tr>", build.id, escape_html(&build.attr), escape_html(&build.name), build.system, escape_html(&build.status.to_string())))?;
+        }
+        if !found {
+            out.write_fmt(format_args!(r#"<tr><td colspan="5" class="none">None 🎉</td></tr>"#))?;
+        }
+        // Bottom
+        out.write_fmt(format_args!(
+            r#"</tbody>
+            </table>
+            <script src="../../js/tables.js"></script>
+          </body>
+        </html>"#
+        ))?;
+    }
+
+    // Render overview over all maintainers
+    let mut maintainer_names: Vec<_> = maintainers.keys().collect();
+    maintainer_names.sort();
+    let mut failed_dir = public_dir.to_path_buf();
+    failed_dir.push("failed");
+    let mut out = failed_dir.clone();
+    out.push("overview.html");
+    let mut out = File::create(out)?;
+    out.write_fmt(format_args!(r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <meta http-equiv="X-UA-Compatible" content="ie=edge">
+        <title>Hydra failures by maintainer</title>
+        <link rel="stylesheet" href="../style.css">
+        <link rel="icon" type="image/x-icon" href="../favicon.ico">
+        <meta property="og:title" content="Hydra failures by maintainer" />
+        <meta property="og:description" content="Overview of maintainers of broken Hydra packages" />
+        <meta property="og:type" content="website" />
+        <meta property="og:url" content="{site_url}/failed/overview.html" />
+        <meta property="og:image" content="../icon.png" />
+      </head>
+      <body id="maintainer-overview">
+        <h1><a href="../index.html" title="Go Home"><img src="../nix-snowflake.svg"></a>Hydra failures by maintainer</h1>
+        <p>If your name is not in this list, then you don't maintain any failed packages. Congratulations!</p>
+        <ul>"#))?;
+    for maintainer_name in maintainer_names {
+        let num_failed = &maintainers.get(maintainer_name).unwrap().len();
+        out.write_fmt(format_args!("<li><a href='by-maintainer/{maintainer_name}.html'>{maintainer_name}</a> ({num_failed})</li>"))?;
+    }
+    out.write_fmt(format_args!("</ul></body></html>"))?;
+
+    // Render the overview over all failed builds
+    let mut all_attrs: Vec<_> = all_failed_builds.keys().collect();
+    all_attrs.sort();
+    // List each derivation under its first attribute
+    let mut listed = HashSet::new();
+    all_attrs.retain(|attr| {
+        let build = &all_failed_builds[*attr].build;
+        unique_of
+            .get(&build.id)
+            .is_none_or(|unique| listed.insert(unique.build.id))
+    });
+    let mut out = failed_dir.clone();
+    out.push("all.html");
+    let mut out = File::create(out)?;
+    out.write_fmt(format_args!(r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <meta http-equiv="X-UA-Compatible" content="ie=edge">
+        <title>All Hydra failures</title>
+        <link rel="stylesheet" href="../style.css">
+        <link rel="icon" type="image/x-icon" href="../favicon.ico">
+        <meta property="og:title" content="All Hydra failures" />
+        <meta property="og:description" content="Overview of all Hydra failures of the most recent evaluations" />
+        <meta property="og:type" content="website" />
+        <meta property="og:url" content="{site_url}/failed/all.html" />
+        <meta property="og:image" content="../icon.png" />
+      </head>
+      <body>
+        <h1><a href="../index.html" title="Go Home"><img src="../nix-snowflake.svg"></a>All Hydra failures</h1>
+        <p>Jump to: <a href='#direct'>Direct Failures</a>&nbsp;&bull;&nbsp;<a href='#indirect'>Indirect Failures</a></p>
+        {MIN_DAYS_FILTER}
+        <h2 id="direct">Direct failures</h2>
+        <p>These are packages fail to build themselves.</p>
+        <table class="sortable">
+            <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Maintainer</th><th>Result</th><th>Failing since</th><th>Log</th></tr></thead>
+            <tbody>"#))?;
+    // Direct failures
+    let mut found = false;
+    for attr in &all_attrs {
+        let MaintainedBuild { maintainer, build } = all_failed_builds.get(*attr).unwrap();
+        if build.status.is_dependency_failure() {
+            continue;
+        }
+        found = true;
+        let since = since_cell(hydra_url, failing_since.get(&build.attr));
+        let log = log_cell(logs.get(&build.id));
+        let badge = flaky_badge(flaky_builds.get(&build.id));
+        let also = also_referenced(build, unique_of.get(&build.id), &jobsets);
+        out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a>{also}</td><td>{}</td><td>{}</td><td>{}</td><td>{}{badge}</td>{since}{log}</tr>", build.id, escape_html(&build.attr), escape_html(&build.name), build.system, escape_html(&maintainer.to_string()), escape_html(&build.status.to_string())))?;
+    }
+    if !found {
+        out.write_fmt(format_args!(
+            r#"<tr><td colspan="7" class="none">None 🎉</td></tr>"#
+        ))?;
+    }
+    // Write middle
+    out.write_fmt(format_args!(r#"</tbody>
+    </table>
+    <p>Jump to: <a href='#direct'>Direct Failures</a>&nbsp;&bull;&nbsp;<a href='#indirect'>Indirect Failures</a></p>
+    <h2 id="indirect">Indirect failures</h2>
+    <p>These are packages where a dependency failed to build.<br></p>
+    <table class="sortable">
+      <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Maintainer</th><th>Result</th><th>Failing since</th></tr></thead>
+      <tbody>"#))?;
+    // Indirect failures
+    let mut found = false;
+    for attr in &all_attrs {
+        let MaintainedBuild { maintainer, build } = all_failed_builds.get(*attr).unwrap();
+        if !build.status.is_dependency_failure() {
+            continue;
+        }
+        found = true;
+        let since = since_cell(hydra_url, failing_since.get(&build.attr));
+        let also = also_referenced(build, unique_of.get(&build.id), &jobsets);
+        out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a>{also}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>{since}</tr>", build.id, escape_html(&build.attr), escape_html(&build.name), build.system, escape_html(&maintainer.to_string()), escape_html(&build.status.to_string())))?;
+    }
+    if !found {
+        out.write_fmt(format_args!(
+            r#"<tr><td colspan="6" class="none">None 🎉</td></tr>"#
+        ))?;
+    }
+    // Write bottom
+    out.write_fmt(format_args!(
+        r#"</tbody></table><script src="../js/tables.js"></script></body></html>"#
+    ))?;
+
+    render_flaky_page(hydra_url, site_url, &flaky, &failed_dir.join("flaky.html"))?;
+
+    Ok(())
+}
+
+/// Renders the list of all attributes that are probably flaky, including the ones that succeed
+/// right now
+fn render_flaky_page(
+    hydra_url: &str,
+    site_url: &str,
+    flaky: &[FlakyAttr],
+    out: &Path,
+) -> Result<()> {
+    let window = diff_evals::FLAKY_WINDOW;
+    let mut out = File::create(out)?;
+    out.write_fmt(format_args!(r#"<!DOCTYPE html>
+    <html lang="en">
+      <head>
+        <meta charset="UTF-8">
+        <meta name="viewport" content="width=device-width, initial-scale=1.0">
+        <meta http-equiv="X-UA-Compatible" content="ie=edge">
+        <title>Probably flaky builds</title>
+        <link rel="stylesheet" href="../style.css">
+        <link rel="icon" type="image/x-icon" href="../favicon.ico">
+        <meta property="og:title" content="Probably flaky builds" />
+        <meta property="og:description" content="Hydra builds that flip between succeeding and failing" />
+        <meta property="og:type" content="website" />
+        <meta property="og:url" content="{site_url}/failed/flaky.html" />
+        <meta property="og:image" content="../icon.png" />
+      </head>
+      <body>
+        <h1><a href="../index.html" title="Go Home"><img src="../nix-snowflake.svg"></a>Probably flaky builds</h1>
+        <p>These are packages that flipped between succeeding and failing in the last {window} evaluations without their derivation changing.</p>
+        <table class="sortable">
+          <thead><tr><th>Attribute</th><th>Job name</th><th>Platform</th><th>Result</th><th>Flips</th><th>Last flipped in</th></tr></thead>
+          <tbody>"#))?;
+    for attr in flaky {
+        let build = &attr.build;
+        let last_flip = &attr.last_flip;
+        out.write_fmt(format_args!("<tr><td><a href=\"{hydra_url}/build/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><a href=\"{hydra_url}/eval/{}\">{}</a></td></tr>", build.id, escape_html(&attr.attr), escape_html(&build.name), build.system, escape_html(&build.status.to_string()), attr.flips, last_flip.id, last_flip.time))?;
+    }
+    if flaky.is_empty() {
+        out.write_fmt(format_args!(
+            r#"<tr><td colspan="6" class="none">None 🎉</td></tr>"#
+        ))?;
+    }
+    out.write_fmt(format_args!(
+        r#"</tbody></table><script src="../js/tables.js"></script></body></html>"#
+    ))?;
+    Ok(())
+}
+
+/// Renders the cell with the evaluation since which a build is failing. Its time is kept for
+/// sorting and filtering the table.
+fn since_cell(hydra_url: &str, since: Option<&StoredEval>) -> String {
+    match since {
+        Some(since) => format!(
+            "<td data-since=\"{}\"><a href=\"{hydra_url}/eval/{}\">{}</a></td>",
+            since.iso_time(),
+            since.id,
+            since.time
+        ),
+        None => "<td>unknown</td>".to_string(),
+    }
+}
+
+/// Renders the other jobsets and attributes the derivation of a build is referenced by
+fn also_referenced(
+    build: &Build,
+    unique: Option<&&UniqueBuild>,
+    jobsets: &HashMap<u64, String>,
+) -> String {
+    let Some(unique) = unique else {
+        return String::new();
+    };
+    // Attributes of nixpkgs evaluations are prefixed in the maintainers cache
+    let eval_attr = build.attr.strip_prefix("nixpkgs.").unwrap_or(&build.attr);
+    let mut also = String::new();
+    for (eval_id, attr) in &unique.references {
+        if *attr == build.attr || attr == eval_attr {
+            continue;
+        }
+        let jobset = jobsets
+            .get(eval_id)
+            .map(String::as_str)
+            .unwrap_or("unknown");
+        also.push_str(&format!(
+            "<br><small class=\"also\">also {} in {}</small>",
+            escape_html(attr),
+            escape_html(jobset)
+        ));
+    }
+    also
+}
+
+/// Renders the badge marking a build that is probably flaky
+fn flaky_badge(flaky: Option<&&FlakyAttr>) -> String {
+    match flaky {
+        Some(flaky) => format!(
+            " <span class=\"flaky\" title=\"Flipped {} times without its derivation changing, last in evaluation {}\">probably flaky</span>",
+            flaky.flips, flaky.last_flip.id
+        ),
+        None => String::new(),
+    }
+}
+
+/// Renders the cell with what went wrong in a build, with the line of its log that gave it away
+/// folded away
+fn log_cell(log: Option<&ClassifiedLog>) -> String {
+    match log {
+        Some(log) if log.line.is_empty() => format!("<td>{}</td>", log.category.title()),
+        Some(log) => format!(
+            "<td><details><summary>{}</summary><code>{}</code></details></td>",
+            log.category.title(),
+            escape_html(&log.line)
+        ),
+        None => "<td>unknown</td>".to_string(),
+    }
+}
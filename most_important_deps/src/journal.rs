@@ -0,0 +1,118 @@
+//! The journal of an evaluation whose builds are still being fetched
+//! (`data/mostimportantcache/{id}.cache.partial`)
+//!
+//! The root causes of every finished build are appended to the journal, followed by a
+//! `#done {build_id}` line. After a crash, everything after the last of these lines belongs to a
+//! build that didn't finish and is dropped, so only the builds that are missing are fetched again.
+//! Once all builds are finished, the cache is published in one atomic rename.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashSet;
+use std::fs::{read_to_string, remove_file, rename, File, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use zhf_core::BlockedBuild;
+
+/// Ends the root causes of a build in the journal
+const DONE_MARKER: &str = "#done ";
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    /// The builds whose root causes are in the journal
+    finished: HashSet<u64>,
+    blocked: Vec<BlockedBuild>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, resuming it if it exists
+    pub fn open(path: &Path) -> Result<Journal> {
+        let mut finished = HashSet::new();
+        let mut blocked = vec![];
+        let mut complete_len = 0;
+        if path.exists() {
+            let contents = read_to_string(path)
+                .with_context(|| format!("Failed reading journal {}", path.display()))?;
+            let mut pending = vec![];
+            let mut offset = 0;
+            for line in contents.split_inclusive('\n') {
+                offset += line.len();
+                // A line without a newline was torn by a crash
+                let Some(line) = line.strip_suffix('\n') else {
+                    break;
+                };
+                if let Some(build_id) = line.strip_prefix(DONE_MARKER) {
+                    finished.insert(build_id.parse().with_context(|| {
+                        format!("Invalid build ID {build_id:?} in {}", path.display())
+                    })?);
+                    blocked.append(&mut pending);
+                    complete_len = offset;
+                } else {
+                    pending.push(line.parse::<BlockedBuild>().with_context(|| {
+                        format!("Malformed line {line:?} in {}", path.display())
+                    })?);
+                }
+            }
+            if !finished.is_empty() {
+                log::info!(
+                    "Resuming {} after {} finished builds",
+                    path.display(),
+                    finished.len()
+                );
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed opening journal {}", path.display()))?;
+        // Drop the builds that didn't finish
+        file.set_len(complete_len as u64)?;
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file,
+            finished,
+            blocked,
+        })
+    }
+
+    /// Whether the root causes of a build are already in the journal
+    pub fn is_finished(&self, build_id: u64) -> bool {
+        self.finished.contains(&build_id)
+    }
+
+    /// Appends the root causes of a finished build
+    pub fn record(&mut self, build_id: u64, blocked: Vec<BlockedBuild>) -> Result<()> {
+        let mut chunk = String::new();
+        for build in &blocked {
+            chunk.push_str(&format!("{build}\n"));
+        }
+        chunk.push_str(&format!("{DONE_MARKER}{build_id}\n"));
+        self.file
+            .write_all(chunk.as_bytes())
+            .with_context(|| format!("Failed writing journal {}", self.path.display()))?;
+        self.finished.insert(build_id);
+        self.blocked.extend(blocked);
+        Ok(())
+    }
+
+    /// Atomically writes all root causes to `cache` and removes the journal
+    pub fn publish(self, cache: &Path) -> Result<()> {
+        let file_name = cache
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Cache {} has no file name", cache.display()))?;
+        let new = cache.with_file_name(format!("{file_name}.new"));
+        {
+            let mut file = File::create(&new)?;
+            for build in &self.blocked {
+                file.write_all(format!("{build}\n").as_bytes())?;
+            }
+            file.sync_all()?;
+        }
+        rename(&new, cache).with_context(|| format!("Failed publishing {}", cache.display()))?;
+        remove_file(&self.path)?;
+        Ok(())
+    }
+}
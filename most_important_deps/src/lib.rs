@@ -0,0 +1,370 @@
+//! Find the root causes of builds that failed with "Dependency failed"
+//!
+//! A failed step of a build either failed in that build, or its failure was propagated from
+//! another build, in which case the step links to that build. These links are followed until the
+//! build the step originally failed in, so every blocked build is blamed on the step that actually
+//! needs fixing.
+
+mod journal;
+
+use anyhow::{anyhow, Context, Result};
+use journal::Journal;
+use reqwest_middleware::ClientWithMiddleware;
+use select::node::Node;
+use select::predicate::{And, Attr, Class, Name, Predicate};
+use std::collections::{HashMap, HashSet};
+use std::fs::create_dir_all;
+use std::path::Path;
+use tokio::sync::mpsc;
+use zhf_core::{parse_lines, store_path_name, BlockedBuild, Eval, FailedDependency, Store, System};
+
+/// How many propagated failures are followed at most to find the root cause of a failed step
+const MAX_PROPAGATION_DEPTH: usize = 16;
+
+/// How many finished builds are logged at once
+const PROGRESS_INTERVAL: usize = 100;
+
+/// How many finished builds can wait for being recorded before fetching the next ones waits
+const CHANNEL_CAPACITY: usize = 100;
+
+/// The suffixes of the files of an evaluation in the cache directory
+const CACHE_SUFFIXES: &[&str] = &[".cache", ".cache.partial", ".cache.new"];
+
+/// The builds of an evaluation that are still being fetched
+struct PendingEval {
+    journal: Journal,
+    remaining: usize,
+    failed: usize,
+}
+
+/// Finds the root causes of all builds of the given evaluations that failed with
+/// "Dependency failed", writes them to `data_dir/mostimportantcache/{eval}.cache` and records them
+/// in the store.
+///
+/// The cache of an evaluation is only written once the root causes of all of its builds were
+/// found. Until then they are kept in a journal, so an interrupted run continues where it
+/// stopped, and builds that failed to be fetched are retried by the next run.
+///
+/// Caches of evaluations that are not given are purged, caches written before root causes were
+/// followed are refreshed.
+pub async fn find_failed_dependencies(
+    http_client: &ClientWithMiddleware,
+    hydra_url: &str,
+    data_dir: &Path,
+    store: &Store,
+    argv: &[u64],
+) -> Result<()> {
+    log::info!("Will crawl evaluations: {:?}", argv);
+
+    // Prepare directories
+    let mut most_important_dir = data_dir.to_path_buf();
+    most_important_dir.push("mostimportantcache");
+    create_dir_all(&most_important_dir)?;
+
+    // Find all builds that are not finished yet
+    let mut evals = HashMap::new();
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    for eval in argv {
+        let cache_loc = most_important_dir.join(format!("{eval}.cache"));
+        if cache_loc.exists() {
+            let contents = std::fs::read_to_string(&cache_loc)?;
+            if parse_lines::<BlockedBuild>(&contents).is_ok() {
+                log::info!("Skipping {eval} because it's already cached");
+                continue;
+            }
+            log::info!("Refreshing the outdated cache of {eval}");
+        }
+
+        let mut eval_loc = data_dir.to_path_buf();
+        eval_loc.push("evalcache");
+        eval_loc.push(format!("{eval}.cache"));
+        let journal = Journal::open(&most_important_dir.join(format!("{eval}.cache.partial")))?;
+        let builds: Vec<(u64, String)> = Eval::read_cache(*eval, &eval_loc)?
+            .builds
+            .into_iter()
+            .filter(|build| build.status.is_dependency_failure())
+            .filter(|build| !journal.is_finished(build.id))
+            .map(|build| (build.id, build.attr))
+            .collect();
+        if builds.is_empty() {
+            journal.publish(&cache_loc)?;
+            continue;
+        }
+
+        // Spawn tasks for getting the root causes, the results are written below
+        for (build_id, attr) in &builds {
+            let tx = tx.clone();
+            let hydra_url = hydra_url.to_string();
+            let http_client = http_client.clone();
+            let (eval, build_id, attr) = (*eval, *build_id, attr.clone());
+            tokio::spawn(async move {
+                let result = fetch_failed_deps_of(&hydra_url, build_id, attr, &http_client).await;
+                // The receiver only goes away if writing failed, which is reported there
+                let _ = tx.send((eval, build_id, result)).await;
+            });
+        }
+        evals.insert(
+            *eval,
+            PendingEval {
+                journal,
+                remaining: builds.len(),
+                failed: 0,
+            },
+        );
+    }
+    drop(tx);
+    let num_build_ids: usize = evals.values().map(|eval| eval.remaining).sum();
+    log::info!("Found {} builds with failed dependencies", num_build_ids);
+
+    // Write all results, publishing the cache of an evaluation once all of its builds are done
+    let mut done = 0;
+    while let Some((eval_id, build_id, result)) = rx.recv().await {
+        let eval = evals
+            .get_mut(&eval_id)
+            .ok_or_else(|| anyhow!("Got a build of unknown evaluation {eval_id}"))?;
+        match result {
+            Ok(blocked) => eval.journal.record(build_id, blocked)?,
+            Err(e) => {
+                log::error!("Failed fetching dependencies of build #{build_id}: {e}");
+                eval.failed += 1;
+            }
+        }
+        eval.remaining -= 1;
+        if eval.remaining == 0 {
+            let eval = evals.remove(&eval_id).unwrap();
+            if eval.failed == 0 {
+                eval.journal
+                    .publish(&most_important_dir.join(format!("{eval_id}.cache")))?;
+            } else {
+                log::error!(
+                    "Not caching evaluation {eval_id} because {} builds failed, they are retried in the next run",
+                    eval.failed
+                );
+            }
+        }
+        done += 1;
+        if done % PROGRESS_INTERVAL == 0 {
+            log::info!("Remaining: {} of {num_build_ids}", num_build_ids - done);
+        }
+    }
+
+    // Record all evaluations the store doesn't know about yet
+    for eval in argv {
+        if !store.blocked_builds(*eval)?.is_empty() {
+            continue;
+        }
+        let cache_loc = most_important_dir.join(format!("{eval}.cache"));
+        if !cache_loc.exists() {
+            continue;
+        }
+        let builds = parse_lines(&std::fs::read_to_string(&cache_loc)?)
+            .with_context(|| format!("Invalid cache {}", cache_loc.display()))?;
+        store.record_blocked_builds(*eval, &builds)?;
+    }
+
+    // Clean cache
+    log::info!("Cleaning cache");
+    for path in std::fs::read_dir(most_important_dir)? {
+        let path = path?;
+        let file_name = path.file_name();
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| anyhow!("Cache entry has no filename"))?;
+        // Ignore none-cache and invalid entries
+        let Some(id) = CACHE_SUFFIXES
+            .iter()
+            .find_map(|suffix| file_name.strip_suffix(suffix))
+            .and_then(|id| id.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        // Ignore entries we know about
+        if !argv.contains(&id) {
+            log::info!("Purging {file_name}");
+            std::fs::remove_file(path.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the root causes of a given build, each one once
+async fn fetch_failed_deps_of(
+    hydra_url: &str,
+    build_id: u64,
+    attr: String,
+    http_client: &ClientWithMiddleware,
+) -> Result<Vec<BlockedBuild>> {
+    // Deduplicate by store path so we don't count the same build failing because of the same
+    // dependency multiple times. This would happen if a whole evaluation is restarted, or if
+    // several failed steps were propagated from the same root cause.
+    let mut causes = HashMap::new();
+    let steps = fetch_build_steps(hydra_url, build_id, http_client).await?;
+    for step in steps.failed {
+        let (store_path, cause) =
+            follow_propagation(hydra_url, build_id, step, steps.system.clone(), http_client).await;
+        causes.insert(store_path, cause);
+    }
+    Ok(causes
+        .into_values()
+        .map(|cause| BlockedBuild {
+            attr: attr.clone(),
+            cause,
+        })
+        .collect())
+}
+
+/// A failed step on the "Build steps" tab of a build
+pub struct FailedStep {
+    /// The first output path of the step
+    pub store_path: String,
+    /// The name of the output path, without the store directory and hash
+    pub name: String,
+    /// The build the step links to, either the build itself or the one the failure was
+    /// propagated from
+    pub build_id: u64,
+    /// Whether the failure was propagated from another build
+    pub propagated: bool,
+    /// The path of the step's log below the Hydra URL (`/build/102/nixlog/1`), if the step
+    /// failed in this build
+    pub log: Option<String>,
+}
+
+/// The failed steps of a build
+pub struct BuildSteps {
+    pub system: System,
+    pub failed: Vec<FailedStep>,
+}
+
+/// Follows the propagated failures of a failed step of `build_id` to the build it originally
+/// failed in. Returns the store path of the step with its root cause. If an origin can't be
+/// fetched, the last known one is blamed.
+async fn follow_propagation(
+    hydra_url: &str,
+    build_id: u64,
+    mut step: FailedStep,
+    mut system: System,
+    http_client: &ClientWithMiddleware,
+) -> (String, FailedDependency) {
+    let mut seen = HashSet::from([build_id]);
+    while step.propagated && seen.len() <= MAX_PROPAGATION_DEPTH && seen.insert(step.build_id) {
+        let origin = match fetch_build_steps(hydra_url, step.build_id, http_client).await {
+            Ok(origin) => origin,
+            Err(e) => {
+                log::warn!(
+                    "Failed following the failure of build #{build_id} to build #{}: {e}",
+                    step.build_id
+                );
+                break;
+            }
+        };
+        match origin
+            .failed
+            .into_iter()
+            .find(|origin_step| origin_step.store_path == step.store_path)
+        {
+            Some(origin_step) => {
+                step = origin_step;
+                system = origin.system;
+            }
+            // The origin is the failed derivation itself
+            None => break,
+        }
+    }
+    let cause = FailedDependency {
+        name: step.name,
+        system,
+        build_id: step.build_id,
+    };
+    (step.store_path, cause)
+}
+
+/// Fetches the failed steps of a build
+pub async fn fetch_build_steps(
+    hydra_url: &str,
+    build_id: u64,
+    http_client: &ClientWithMiddleware,
+) -> Result<BuildSteps> {
+    let res = http_client
+        .get(format!("{hydra_url}/build/{build_id}"))
+        // Only pages of finished builds are fetched
+        .with_extension(hydra_client::Immutable)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let doc = select::document::Document::from(&res[..]);
+
+    // Find architecture
+    let system = doc
+        .find(Class("info-table").descendant(Name("tt")))
+        .take(1)
+        .next()
+        .ok_or_else(|| anyhow!("No architecture found"))?
+        .text()
+        .parse::<System>()?;
+    log::debug!("Detected architecture {system}");
+
+    // Find all failed steps
+    let mut failed = vec![];
+    let rows = doc
+        .find(Attr("id", "tabs-buildsteps").descendant(And(Name("table"), Class("clickable-rows"))))
+        .next()
+        .ok_or_else(|| anyhow!("No build steps found"))?
+        .find(Name("tr"));
+    for row in rows {
+        let cols: Vec<Node> = row.find(Name("td")).collect();
+        if cols.len() != 5 {
+            continue;
+        }
+        // Ignore non-failed steps
+        let status = cols[4].text();
+        if !status.contains("Failed") && !status.contains("Cached") {
+            continue;
+        }
+        // Find all links
+        let mut link_to_return = None;
+        let mut propagated = false;
+        let mut log = None;
+        for link in cols[4].find(Name("a")) {
+            // Use the log link
+            if link_to_return.is_none() && link.text() == "log" {
+                link_to_return = link.attr("href");
+                log = link
+                    .attr("href")
+                    .and_then(|href| href.find("/build/").map(|i| href[i..].to_string()));
+            }
+            // Prefer the propagated build link
+            if link.text().starts_with("build ") {
+                link_to_return = link.attr("href");
+                propagated = true;
+            }
+        }
+        let Some(link) = link_to_return else {
+            // This happens when a build is retried
+            continue;
+        };
+        let store_path = cols[1]
+            .find(Name("tt"))
+            .next()
+            .ok_or_else(|| anyhow!("No store path found"))?
+            .text();
+        let store_path = store_path.split(',').next().unwrap();
+        let name = store_path_name(store_path)
+            .ok_or_else(|| anyhow!("Invalid store path {store_path:?}"))?;
+        let build_id = link
+            .split('/')
+            .nth(4)
+            .ok_or_else(|| anyhow!("No build ID found"))?
+            .parse()?;
+        failed.push(FailedStep {
+            store_path: store_path.to_owned(),
+            name: name.to_owned(),
+            build_id,
+            propagated,
+            log: log.filter(|_| !propagated),
+        });
+    }
+    Ok(BuildSteps { system, failed })
+}
@@ -0,0 +1,129 @@
+//! Run `most_important_deps` against recorded Hydra pages
+
+use hydra_fixtures::{pages_dir, FixtureServer};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Reads a cache file with its lines sorted, as builds are fetched in parallel
+fn read_sorted(path: &Path) -> Vec<String> {
+    let mut lines: Vec<String> = std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// The golden caches of the tests
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Runs `most_important_deps` on evals 1001 and 2000 in `work_dir`, returning the cache directory
+fn run(work_dir: &Path) -> PathBuf {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let golden = golden_dir();
+    let data_dir = work_dir.join("data");
+    std::fs::create_dir_all(data_dir.join("evalcache")).unwrap();
+    for eval in ["1001", "2000"] {
+        std::fs::copy(
+            golden.join("evalcache").join(format!("{eval}.cache")),
+            data_dir.join("evalcache").join(format!("{eval}.cache")),
+        )
+        .unwrap();
+    }
+
+    let status = Command::new(env!("CARGO_BIN_EXE_most_important_deps"))
+        .args(["1001", "2000"])
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    data_dir.join("mostimportantcache")
+}
+
+/// Runs `most_important_deps` and compares the caches to the golden ones
+fn run_and_compare(work_dir: &Path) {
+    let cache_dir = run(work_dir);
+    let golden = golden_dir();
+    for eval in ["1001", "2000"] {
+        let file = format!("{eval}.cache");
+        assert_eq!(
+            read_sorted(&cache_dir.join(&file)),
+            read_sorted(&golden.join("mostimportantcache").join(&file)),
+        );
+    }
+}
+
+#[test]
+fn finds_root_causes() {
+    let work_dir = tempfile::tempdir().unwrap();
+    run_and_compare(work_dir.path());
+}
+
+#[test]
+fn refreshes_caches_without_blocked_attrs() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let cache_dir = work_dir.path().join("data").join("mostimportantcache");
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    std::fs::write(cache_dir.join("1001.cache"), "foo-1.0;x86_64-linux;103\n").unwrap();
+    run_and_compare(work_dir.path());
+}
+
+#[test]
+fn resumes_the_journal_of_an_interrupted_run() {
+    let work_dir = tempfile::tempdir().unwrap();
+    let cache_dir = work_dir.path().join("data").join("mostimportantcache");
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    // Build 103 finished, build 105 was torn while being written
+    std::fs::write(
+        cache_dir.join("1001.cache.partial"),
+        "old-1.0;x86_64-linux;100;nixpkgs.bar.x86_64-linux\n#done 103\nfoo-1.0;x86_64-li",
+    )
+    .unwrap();
+    run(work_dir.path());
+    assert_eq!(
+        read_sorted(&cache_dir.join("1001.cache")),
+        [
+            "foo-1.0;x86_64-linux;102;nixos.tests.simple.x86_64-linux",
+            "old-1.0;x86_64-linux;100;nixpkgs.bar.x86_64-linux"
+        ]
+    );
+    assert!(!cache_dir.join("1001.cache.partial").exists());
+}
+
+#[test]
+fn keeps_the_journal_if_a_build_fails() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let data_dir = work_dir.path().join("data");
+    std::fs::create_dir_all(data_dir.join("evalcache")).unwrap();
+    std::fs::write(
+        data_dir.join("evalcache").join("1002.cache"),
+        "nixpkgs.bar.x86_64-linux 103 bar-2.0 x86_64-linux Dependency failed\n\
+         nixpkgs.gone.x86_64-linux 999 gone-1.0 x86_64-linux Dependency failed\n",
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_most_important_deps"))
+        .arg("1002")
+        .env("HYDRA_URL", server.url())
+        .current_dir(work_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let cache_dir = data_dir.join("mostimportantcache");
+    assert!(!cache_dir.join("1002.cache").exists());
+    assert_eq!(
+        read_sorted(&cache_dir.join("1002.cache.partial")),
+        [
+            "#done 103",
+            "foo-1.0;x86_64-linux;102;nixpkgs.bar.x86_64-linux"
+        ]
+    );
+}
@@ -0,0 +1,57 @@
+// Sorting and filtering of the failed builds tables.
+//
+// Clicking a header of a `table.sortable` sorts its rows by that column, clicking it again
+// reverses the order. Cells with a `data-since` attribute are sorted by it instead of their text.
+// The `#min-days` input hides all builds that are failing for fewer days, it can be preset with
+// `?min-days=N`.
+
+(function () {
+  const sortKey = cell => cell.dataset.since || cell.textContent.trim();
+
+  document.querySelectorAll('table.sortable').forEach(table => {
+    table.querySelectorAll('thead th').forEach((header, column) => {
+      header.style.cursor = 'pointer';
+      header.title = 'Sort by this column';
+      header.addEventListener('click', () => {
+        const ascending = header.dataset.order !== 'asc';
+        table.querySelectorAll('thead th').forEach(th => delete th.dataset.order);
+        header.dataset.order = ascending ? 'asc' : 'desc';
+        const tbody = table.querySelector('tbody');
+        const rows = Array.from(tbody.querySelectorAll('tr')).filter(row => !row.querySelector('td.none'));
+        rows.sort((a, b) => {
+          const x = sortKey(a.cells[column]);
+          const y = sortKey(b.cells[column]);
+          return (ascending ? 1 : -1) * x.localeCompare(y);
+        });
+        rows.forEach(row => tbody.appendChild(row));
+      });
+    });
+  });
+
+  const minDays = document.getElementById('min-days');
+  if (!minDays) {
+    return;
+  }
+  const filter = () => {
+    const days = Number(minDays.value) || 0;
+    const newest = Date.now() - days * 24 * 60 * 60 * 1000;
+    document.querySelectorAll('table.sortable tbody tr').forEach(row => {
+      if (row.querySelector('td.none')) {
+        return;
+      }
+      const cell = row.querySelector('td[data-since]');
+      const since = cell ? Date.parse(cell.dataset.since) : NaN;
+      row.hidden = days > 0 && !(since <= newest);
+    });
+    const url = new URL(window.location);
+    if (days > 0) {
+      url.searchParams.set('min-days', days);
+    } else {
+      url.searchParams.delete('min-days');
+    }
+    window.history.replaceState(null, '', url);
+  };
+  minDays.value = new URLSearchParams(window.location.search).get('min-days') || 0;
+  minDays.addEventListener('input', filter);
+  filter();
+})();
@@ -0,0 +1,175 @@
+//! The burndown history, kept in the evals of the store
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use crawl_jobset::JobsetEval;
+use reqwest_middleware::ClientWithMiddleware;
+use std::collections::{BTreeSet, HashSet};
+use std::fs::read_to_string;
+use std::path::Path;
+use zhf_core::{Branch, BuildStatus, Jobset, Store, StoredEval};
+
+/// Records the latest evaluation of a jobset unless it's already recorded
+pub fn record(store: &Store, branch: &Branch, jobset: &Jobset, eval: &JobsetEval) -> Result<()> {
+    store.record_eval(&StoredEval {
+        id: eval.id,
+        branch: branch.name.clone(),
+        jobset: jobset.name.clone(),
+        failures: eval.failures,
+        time: eval.time.clone(),
+    })
+}
+
+/// Records the finished evaluations of a jobset that happened between `from` and `to` together with
+/// their failure counts, returning how many of them weren't recorded yet
+pub async fn backfill(
+    store: &Store,
+    http_client: &ClientWithMiddleware,
+    branch: &Branch,
+    jobset: &Jobset,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<usize> {
+    let recorded: HashSet<u64> = store
+        .evals(&branch.name, &jobset.name)?
+        .iter()
+        .map(|eval| eval.id)
+        .collect();
+    let evals = crawl_jobset::evals_between(
+        http_client,
+        &branch.hydra_url(),
+        &jobset.project,
+        &jobset.jobset,
+        from,
+        to,
+    )
+    .await?;
+    let counted = store.failure_breakdowns(&branch.name, &jobset.name)?;
+    let mut added = 0;
+    for eval in evals {
+        // Like `zhf run`, skip evals with unfinished builds and fully failed evals (no builds)
+        if !eval.is_finished() || eval.successes == 0 {
+            log::debug!("Skipping unfinished eval {}", eval.id);
+            continue;
+        }
+        if !recorded.contains(&eval.id) {
+            record(store, branch, jobset, &eval)?;
+            added += 1;
+        }
+        if !counted.contains_key(&eval.id) {
+            let breakdown = crawl_evals::fetch_failure_breakdown(
+                http_client,
+                &branch.hydra_url(),
+                eval.id,
+                jobset,
+            )
+            .await?;
+            store.record_failure_breakdown(eval.id, &breakdown)?;
+        }
+    }
+    Ok(added)
+}
+
+/// Parses the bound of a backfilled range, either a time in RFC 3339 or a date. A date stands for
+/// its start, or its end if `end_of_day` is set.
+pub fn parse_bound(s: &str, end_of_day: bool) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date {s:?}, expected YYYY-MM-DD or RFC 3339"))?;
+    let time = if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    let time = time.expect("Midnight and the last second exist");
+    Ok(DateTime::from_utc(time, Utc))
+}
+
+/// Moves the history files `history-{jobset}` of the branch in `dir`, which were kept before
+/// there was a store, into the store
+pub fn import(store: &Store, branch: &Branch, dir: &Path) -> Result<()> {
+    for jobset in &branch.jobsets {
+        let path = dir.join(format!("history-{}", jobset.name));
+        if !path.exists() {
+            continue;
+        }
+        log::info!("Importing {} into the store", path.display());
+        let contents =
+            read_to_string(&path).with_context(|| format!("Failed reading {}", path.display()))?;
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(3, ' ');
+            let (Some(id), Some(failures), Some(time)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(anyhow!(
+                    "Malformed history line {line:?} in {}",
+                    path.display()
+                ));
+            };
+            store.record_eval(&StoredEval {
+                id: id.parse()?,
+                branch: branch.name.clone(),
+                jobset: jobset.name.clone(),
+                failures: failures.parse()?,
+                time: time.to_string(),
+            })?;
+        }
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// The history of a jobset as Chart.js data points
+pub struct Burndown<'a> {
+    pub jobset: &'a Jobset,
+    /// The failures reported by Hydra
+    pub total: String,
+    /// The failed builds with each status, for the evaluations whose failure counts are recorded
+    pub by_status: Vec<(BuildStatus, String)>,
+}
+
+/// Renders the history of a jobset as Chart.js data points
+pub fn burndown<'a>(store: &Store, branch: &Branch, jobset: &'a Jobset) -> Result<Burndown<'a>> {
+    let mut total = String::new();
+    let mut breakdowns = vec![];
+    let mut counted = store.failure_breakdowns(&branch.name, &jobset.name)?;
+    for eval in store.evals(&branch.name, &jobset.name)? {
+        let date = eval.time.trim_end_matches(" (UTC)");
+        let date = match NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S") {
+            Ok(date) => date,
+            Err(e) => {
+                log::warn!("Skipping history entry with invalid date {date:?}: {e}");
+                continue;
+            }
+        };
+        let x = date.format("%Y-%m-%dT%H:%M:%S").to_string();
+        total.push_str(&format!("{{ x: '{x}', y: '{}' }},", eval.failures));
+        if let Some(breakdown) = counted.remove(&eval.id) {
+            breakdowns.push((x, breakdown));
+        }
+    }
+
+    let statuses: BTreeSet<BuildStatus> = breakdowns
+        .iter()
+        .flat_map(|(_, breakdown)| breakdown.by_status().into_keys().cloned())
+        .collect();
+    let by_status = statuses
+        .into_iter()
+        .map(|status| {
+            let points = breakdowns
+                .iter()
+                .map(|(x, breakdown)| {
+                    let num = breakdown.by_status().get(&status).copied().unwrap_or(0);
+                    format!("{{ x: '{x}', y: '{num}' }},")
+                })
+                .collect();
+            (status, points)
+        })
+        .collect();
+    Ok(Burndown {
+        jobset,
+        total,
+        by_status,
+    })
+}
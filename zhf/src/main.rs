@@ -0,0 +1,84 @@
+//! Runs the whole ZHF pipeline and renders the pages to `public/`
+//!
+//! Usage: `zhf run [--offline] [branch...]`, all configured branches are processed if none are
+//! given. With `--offline`, Hydra is not asked and only cached answers are used.
+//!
+//! `zhf backfill [--offline] branch jobset from [to]` records the finished evaluations of a jobset
+//! between two dates (`YYYY-MM-DD` or RFC 3339, `to` defaults to now) in the burndown history,
+//! with their failures counted by status from their pages. Evaluations that are already recorded
+//! are kept.
+
+mod history;
+mod page;
+mod pipeline;
+
+use anyhow::{anyhow, Result};
+use pipeline::Paths;
+use zhf_core::{Config, Store};
+
+const USAGE: &str =
+    "Usage: zhf run [--offline] [branch...] | zhf backfill [--offline] branch jobset from [to]";
+
+#[tokio::main(worker_threads = 4)]
+async fn main() -> Result<()> {
+    env_logger::builder().format_timestamp(None).init();
+    // Handle args
+    let mut argv: Vec<String> = std::env::args().skip(1).collect();
+    let offline = hydra_client::take_offline_flag(&mut argv);
+    let config = Config::load()?;
+    match argv.first().map(String::as_str) {
+        Some("run") => {}
+        Some("backfill") => return backfill(&config, &argv[1..], offline).await,
+        _ => return Err(anyhow!(USAGE)),
+    }
+    let branches = if argv.len() > 1 {
+        argv[1..]
+            .iter()
+            .map(|name| config.branch(Some(name)))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        config.branches.iter().collect()
+    };
+
+    let paths = Paths::from_current_dir()?;
+    if let Err(e) = pipeline::run(&paths, &config.http, offline, &branches).await {
+        log::error!("{e}");
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Fills the burndown history of a jobset with the evaluations Hydra still lists
+async fn backfill(config: &Config, args: &[String], offline: bool) -> Result<()> {
+    let (branch, jobset, from, to) = match args {
+        [branch, jobset, from] => (branch, jobset, from, None),
+        [branch, jobset, from, to] => (branch, jobset, from, Some(to)),
+        _ => return Err(anyhow!(USAGE)),
+    };
+    let branch = config.branch(Some(branch))?;
+    let jobset = branch
+        .jobsets
+        .iter()
+        .find(|j| &j.name == jobset)
+        .ok_or_else(|| anyhow!("Branch {} has no jobset {jobset}", branch.name))?;
+    let from = history::parse_bound(from, false)?;
+    let to = match to {
+        Some(to) => history::parse_bound(to, true)?,
+        None => chrono::Utc::now(),
+    };
+    if from > to {
+        return Err(anyhow!("The range starts after it ends"));
+    }
+
+    let paths = Paths::from_current_dir()?;
+    let (http_client, metrics) = hydra_client::client(&config.http, &paths.data_dir, offline)?;
+    let store = Store::open_in(&paths.data_dir)?;
+    let added = history::backfill(&store, &http_client, branch, jobset, from, to).await?;
+    log::info!(
+        "Recorded {added} new evals of {}:{} in the history",
+        branch.name,
+        jobset.name
+    );
+    log::info!("Hydra requests: {metrics}");
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Everything that ends up on the index page
+
+use crate::history::Burndown;
+use anyhow::{Context, Result};
+use crawl_jobset::JobsetEval;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, rename, write};
+use std::path::Path;
+use zhf_core::{
+    dedup_builds, escape_html, parse_lines, BlockedBuild, Branch, Eval, FailedDependency,
+    FailureBreakdown, Jobset,
+};
+
+/// How many dependencies are listed in the most problematic dependencies table
+const MOST_PROBLEMATIC_DEPS: usize = 30;
+
+/// The version of the counts in the fail cache, bumped when builds are counted differently so
+/// caches of older versions are recounted
+const FAIL_CACHE_VERSION: u32 = 2;
+
+/// The template of the overview of all branches, it's not copied to the rendered page
+const OVERVIEW_TEMPLATE: &str = "branches.html";
+
+/// What the overview shows about a branch
+pub struct BranchSummary<'a> {
+    pub branch: &'a Branch,
+    /// The latest finished evaluation of each jobset
+    pub evals: Vec<(&'a Jobset, JobsetEval)>,
+    /// Failed builds of all jobsets, deduplicated by derivation
+    pub total_failures: u64,
+}
+
+/// Counts the failed builds of the evaluations by system and status, deduplicated by derivation.
+///
+/// The result is cached in `data/failcache/{eval ids}.v{version}.cache` and caches of other
+/// evaluations or versions are purged.
+pub fn failure_breakdown(data_dir: &Path, eval_ids: &[u64]) -> Result<FailureBreakdown> {
+    let fail_cache = data_dir.join("failcache");
+    create_dir_all(&fail_cache)?;
+    let key = eval_ids
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let cache_file = fail_cache.join(format!("{key}.v{FAIL_CACHE_VERSION}.cache"));
+
+    let cached = if cache_file.exists() {
+        match FailureBreakdown::parse_cache(&read_to_string(&cache_file)?) {
+            Ok(breakdown) => Some(breakdown),
+            Err(e) => {
+                // Caches written before statuses were counted only have systems
+                log::info!("Refreshing fail cache {}: {e:#}", cache_file.display());
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let breakdown = match cached {
+        Some(breakdown) => breakdown,
+        None => {
+            let mut evals = vec![];
+            for eval_id in eval_ids {
+                evals.push(Eval::read_cache(
+                    *eval_id,
+                    &data_dir.join("evalcache").join(format!("{eval_id}.cache")),
+                )?);
+            }
+            let unique = dedup_builds(&evals);
+            let breakdown =
+                FailureBreakdown::from_builds(unique.iter().map(|unique| &unique.build));
+            let new_file = fail_cache.join(format!("{key}.cache.new"));
+            write(&new_file, breakdown.to_cache())?;
+            rename(new_file, &cache_file)?;
+            breakdown
+        }
+    };
+
+    // Clean cache
+    for entry in read_dir(&fail_cache)? {
+        let entry = entry?;
+        if entry.path() != cache_file {
+            log::info!("Purging fail cache {:?}", entry.file_name());
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(breakdown)
+}
+
+/// Renders the table rows of the root causes that block the most jobs, each with the list of jobs
+/// it blocks
+pub fn most_problematic_deps(hydra_url: &str, data_dir: &Path) -> Result<String> {
+    let mut blocked: HashMap<FailedDependency, BTreeSet<String>> = HashMap::new();
+    for entry in read_dir(data_dir.join("mostimportantcache"))? {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("cache") {
+            continue;
+        }
+        let builds = parse_lines::<BlockedBuild>(&read_to_string(&path)?)
+            .with_context(|| format!("Invalid cache {}", path.display()))?;
+        for build in builds {
+            blocked.entry(build.cause).or_default().insert(build.attr);
+        }
+    }
+    let mut blocked: Vec<(FailedDependency, BTreeSet<String>)> = blocked.into_iter().collect();
+    blocked.sort_by(|(a_dep, a_attrs), (b_dep, b_attrs)| {
+        b_attrs
+            .len()
+            .cmp(&a_attrs.len())
+            .then_with(|| a_dep.cmp(b_dep))
+    });
+
+    let mut rows = String::new();
+    for (dep, attrs) in blocked.into_iter().take(MOST_PROBLEMATIC_DEPS) {
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{hydra_url}/build/{}\">{}</a></td><td>{}</td><td><details><summary>{}</summary>{}</details></td></tr>",
+            dep.build_id,
+            escape_html(&dep.name),
+            dep.system,
+            attrs.len(),
+            attrs
+                .iter()
+                .map(|attr| escape_html(attr))
+                .collect::<Vec<_>>()
+                .join("<br>")
+        ));
+    }
+    Ok(rows)
+}
+
+/// Renders the table rows with the failures per system, split up by status, and the total
+pub fn failing_builds_table(breakdown: &FailureBreakdown) -> (String, u64) {
+    let mut table = String::new();
+    for (system, num) in breakdown.by_system() {
+        let statuses = breakdown
+            .statuses_of(system)
+            .into_iter()
+            .map(|(status, num)| format!("{num} {}", status.to_string().to_lowercase()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        table.push_str(&format!(
+            "<tr><td>Failing builds on {system}:</td><td><b>{num}</b> ({statuses})</td></tr>"
+        ));
+    }
+    (table, breakdown.total())
+}
+
+/// Line colors of the burndown chart, used in the order of the jobsets
+const BURNDOWN_COLORS: &[&str] = &["#4d6fb6", "#7eb6e1", "#e1a97e", "#7ee1a9", "#b64d6f"];
+
+/// Renders the table rows with the latest evaluation of each jobset
+pub fn eval_rows(hydra_url: &str, evals: &[(&Jobset, &JobsetEval)]) -> String {
+    let mut rows = String::new();
+    for (jobset, eval) in evals {
+        rows.push_str(&format!(
+            "<tr><td>Latest {} evaluation (completely built):</td><td><a href=\"{hydra_url}/eval/{}\"><b>{}</b></a> on <b>{}</b></td></tr>",
+            jobset.title, eval.id, eval.id, eval.time
+        ));
+    }
+    rows
+}
+
+/// Quotes text as a JavaScript string literal that can't end the inline `<script>` it's in
+fn js_string(text: String) -> String {
+    serde_json::Value::String(text)
+        .to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
+
+/// Renders one burndown chart dataset per jobset from its history points, followed by a dashed
+/// dataset per status that is hidden until it's picked in the legend
+pub fn burndown_datasets(burndowns: &[Burndown]) -> String {
+    let mut datasets = vec![];
+    for (burndown, color) in burndowns.iter().zip(BURNDOWN_COLORS.iter().cycle()) {
+        let title = &burndown.jobset.title;
+        datasets.push(format!(
+            "{{ label: {}, borderColor: '{color}', lineTension: 0, data: [{}] }}",
+            js_string(format!("{title} Failures")),
+            burndown.total
+        ));
+        for (status, points) in &burndown.by_status {
+            let label = js_string(format!("{title} {status}"));
+            datasets.push(format!(
+                "{{ label: {label}, borderColor: '{color}', borderDash: [4, 4], hidden: true, lineTension: 0, data: [{points}] }}"
+            ));
+        }
+    }
+    datasets.join(", ")
+}
+
+/// Renders the table rows of the overview, one per branch
+pub fn branch_rows(summaries: &[BranchSummary]) -> String {
+    let mut rows = String::new();
+    for summary in summaries {
+        let name = &summary.branch.name;
+        let evals = summary
+            .evals
+            .iter()
+            .map(|(jobset, eval)| format!("{} <b>{}</b> on {}", jobset.title, eval.id, eval.time))
+            .collect::<Vec<_>>()
+            .join("<br>");
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{name}/\">{name}</a></td><td><b>{}</b></td><td>{evals}</td></tr>",
+            summary.total_failures
+        ));
+    }
+    rows
+}
+
+/// Copies the static page to `public_dir` and fills in the placeholders of the index
+pub fn render_index(page_dir: &Path, public_dir: &Path, values: &[(&str, String)]) -> Result<()> {
+    copy_dir_all(page_dir, public_dir)?;
+    render_template(&public_dir.join("index.html"), values)
+}
+
+/// Copies the static page to `public_dir` and renders the overview of all branches as its index
+pub fn render_overview(
+    page_dir: &Path,
+    public_dir: &Path,
+    values: &[(&str, String)],
+) -> Result<()> {
+    copy_dir_all(page_dir, public_dir)?;
+    let index = public_dir.join("index.html");
+    copy(page_dir.join(OVERVIEW_TEMPLATE), &index)?;
+    render_template(&index, values)
+}
+
+/// Fills in the `@name@` placeholders of a file
+fn render_template(path: &Path, values: &[(&str, String)]) -> Result<()> {
+    let mut contents = read_to_string(path)?;
+    for (name, value) in values {
+        contents = contents.replace(&format!("@{name}@"), value);
+    }
+    write(path, contents)?;
+    Ok(())
+}
+
+/// Recursively copies a directory, except for the overview template
+fn copy_dir_all(from: &Path, to: &Path) -> Result<()> {
+    create_dir_all(to)?;
+    for entry in read_dir(from).with_context(|| format!("Failed reading {}", from.display()))? {
+        let entry = entry?;
+        if entry.file_name() == OVERVIEW_TEMPLATE {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zhf_core::BuildStatus;
+
+    #[test]
+    fn quotes_dataset_labels() {
+        let jobset = Jobset {
+            name: "linux".to_string(),
+            title: "Linux".to_string(),
+            project: "nixos".to_string(),
+            jobset: "trunk-combined".to_string(),
+            systems: None,
+        };
+        let burndown = Burndown {
+            jobset: &jobset,
+            total: String::new(),
+            by_status: vec![(
+                BuildStatus::Unknown("it's </script>".to_string()),
+                String::new(),
+            )],
+        };
+        let datasets = burndown_datasets(&[burndown]);
+        assert!(datasets.contains("{ label: \"Linux Failures\","));
+        assert!(datasets.contains("{ label: \"Linux it's \\u003c/script\\u003e\","));
+    }
+
+    #[test]
+    fn escapes_problematic_deps() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let cache_dir = data_dir.path().join("mostimportantcache");
+        create_dir_all(&cache_dir).unwrap();
+        write(
+            cache_dir.join("1001.cache"),
+            "foo&bar-1.0;x86_64-linux;102;a<b>.x86_64-linux\n",
+        )
+        .unwrap();
+        let rows = most_problematic_deps("https://hydra.nixos.org", data_dir.path()).unwrap();
+        assert!(rows.contains(">foo&amp;bar-1.0</a>"));
+        assert!(rows.contains("<summary>1</summary>a&lt;b&gt;.x86_64-linux</details>"));
+    }
+}
@@ -0,0 +1,378 @@
+//! The whole pipeline, from asking Hydra about the latest evaluations to rendering the page
+
+use crate::page::BranchSummary;
+use crate::{history, page};
+use anyhow::{anyhow, Context, Result};
+use crawl_evals::Backend;
+use crawl_jobset::JobsetEval;
+use reqwest_middleware::ClientWithMiddleware;
+use std::fmt;
+use std::fs::{create_dir_all, read_dir, remove_dir_all};
+use std::path::{Path, PathBuf};
+use zhf_core::{Branch, HttpConfig, Jobset, Store};
+
+/// A step of the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    CrawlJobsets,
+    CrawlEvals,
+    CountFailures,
+    DiffEvals,
+    FetchMaintainers,
+    ClassifyLogs,
+    MaintainerPages,
+    MostImportantDeps,
+    RenderPage,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Step::CrawlJobsets => "crawl_jobset",
+            Step::CrawlEvals => "crawl_evals",
+            Step::CountFailures => "count_failures",
+            Step::DiffEvals => "diff_evals",
+            Step::FetchMaintainers => "fetch_maintainers",
+            Step::ClassifyLogs => "build_logs",
+            Step::MaintainerPages => "maintainer_pages",
+            Step::MostImportantDeps => "most_important_deps",
+            Step::RenderPage => "render_page",
+        })
+    }
+}
+
+/// The error of the step of the pipeline that failed
+#[derive(Debug)]
+pub struct StepError {
+    pub step: Step,
+    pub source: anyhow::Error,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Step {} failed: {:#}", self.step, self.source)
+    }
+}
+
+impl std::error::Error for StepError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Attributes errors to a step of the pipeline
+trait InStep<T> {
+    fn in_step(self, step: Step) -> Result<T, StepError>;
+}
+
+impl<T> InStep<T> for Result<T> {
+    fn in_step(self, step: Step) -> Result<T, StepError> {
+        self.map_err(|source| StepError { step, source })
+    }
+}
+
+/// Where the pipeline reads and writes its files
+pub struct Paths {
+    /// Caches and history, kept between runs
+    pub data_dir: PathBuf,
+    /// The rendered website, recreated on every run
+    pub public_dir: PathBuf,
+    /// The static parts of the website
+    pub page_dir: PathBuf,
+}
+
+impl Paths {
+    /// The layout of a branch, namespaced below the directories of all branches
+    pub fn branch(&self, name: &str) -> Paths {
+        Paths {
+            data_dir: self.data_dir.join(name),
+            public_dir: self.public_dir.join(name),
+            page_dir: self.page_dir.clone(),
+        }
+    }
+
+    /// The usual layout below the current directory
+    pub fn from_current_dir() -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        Ok(Paths {
+            data_dir: cwd.join("data"),
+            public_dir: cwd.join("public"),
+            page_dir: cwd.join("page"),
+        })
+    }
+}
+
+/// Removes cache entries named `{eval}.*` of evaluations that are not in `eval_ids`
+fn purge_cache(cache_dir: &Path, eval_ids: &[u64]) -> Result<()> {
+    for entry in read_dir(cache_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let id = name.split('.').next().and_then(|id| id.parse::<u64>().ok());
+        if id.map(|id| !eval_ids.contains(&id)).unwrap_or(true) {
+            log::info!("Purging {}", entry.path().display());
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the whole pipeline for each of the branches and renders an overview of them. All requests
+/// to Hydra share the limits of `http`, with `offline` only cached answers are used.
+pub async fn run(
+    paths: &Paths,
+    http: &HttpConfig,
+    offline: bool,
+    branches: &[&Branch],
+) -> Result<(), StepError> {
+    let Paths {
+        data_dir,
+        public_dir,
+        page_dir,
+    } = paths;
+    if public_dir.exists() {
+        remove_dir_all(public_dir)
+            .context("Failed removing the old page")
+            .in_step(Step::RenderPage)?;
+    }
+    create_dir_all(public_dir)
+        .and_then(|_| create_dir_all(data_dir))
+        .context("Failed creating directories")
+        .in_step(Step::RenderPage)?;
+
+    let (http_client, metrics) =
+        hydra_client::client(http, data_dir, offline).in_step(Step::CrawlJobsets)?;
+
+    let store = Store::open_in(data_dir).in_step(Step::CrawlJobsets)?;
+
+    let mut summaries = vec![];
+    for branch in branches {
+        let branch_paths = paths.branch(&branch.name);
+        // Only master was tracked before the data of each branch had its own directory
+        if branch.name == "master" {
+            history::import(&store, branch, data_dir).in_step(Step::CrawlJobsets)?;
+        }
+        history::import(&store, branch, &branch_paths.data_dir).in_step(Step::CrawlJobsets)?;
+        let summary = run_branch(&branch_paths, branch, &store, &http_client)
+            .await
+            .map_err(|StepError { step, source }| StepError {
+                step,
+                source: source.context(format!("Branch {}", branch.name)),
+            })?;
+        summaries.push(summary);
+    }
+
+    log::info!("Rendering overview...");
+    page::render_overview(
+        page_dir,
+        public_dir,
+        &[
+            ("siteurl", branches[0].site_url().to_string()),
+            ("branchrows", page::branch_rows(&summaries)),
+            ("lastcheck", last_check()),
+            ("triggered", triggered_by()),
+        ],
+    )
+    .in_step(Step::RenderPage)?;
+
+    log::info!("Hydra requests: {metrics}");
+    Ok(())
+}
+
+/// When the pipeline ran, as shown on the page
+fn last_check() -> String {
+    chrono::Utc::now()
+        .format("%Y-%m-%d %H:%M:%S (UTC)")
+        .to_string()
+}
+
+/// What triggered the CI pipeline, as shown on the page
+fn triggered_by() -> String {
+    std::env::var("CI_PIPELINE_SOURCE").unwrap_or_else(|_| "???".to_string())
+}
+
+/// Runs the whole pipeline for a single branch, rendering its page to its own public directory
+async fn run_branch<'a>(
+    paths: &Paths,
+    branch: &'a Branch,
+    store: &Store,
+    http_client: &ClientWithMiddleware,
+) -> Result<BranchSummary<'a>, StepError> {
+    let Paths {
+        data_dir,
+        public_dir,
+        page_dir,
+    } = paths;
+    create_dir_all(public_dir)
+        .and_then(|_| create_dir_all(data_dir))
+        .context("Failed creating directories")
+        .in_step(Step::RenderPage)?;
+    let hydra_url = branch.hydra_url();
+
+    // Gather data
+    log::info!(
+        "Target branch is {} (jobsets {})",
+        branch.name,
+        branch
+            .jobsets
+            .iter()
+            .map(|jobset| format!("{}/{}", jobset.project, jobset.jobset))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let mut latest = vec![];
+    for jobset in &branch.jobsets {
+        log::info!("Asking Hydra about {}...", jobset.name);
+        let eval = crawl_jobset::latest_finished_eval(
+            http_client,
+            &hydra_url,
+            &jobset.project,
+            &jobset.jobset,
+        )
+        .await
+        .in_step(Step::CrawlJobsets)?;
+        history::record(store, branch, jobset, &eval).in_step(Step::CrawlJobsets)?;
+        latest.push((jobset, eval));
+    }
+
+    let mut evals: Vec<(u64, &Jobset)> = latest
+        .iter()
+        .map(|(jobset, eval)| (eval.id, *jobset))
+        .collect();
+    evals.sort_unstable_by_key(|(id, _)| *id);
+    let eval_ids: Vec<u64> = evals.iter().map(|(id, _)| *id).collect();
+    log::info!("Evaluations are {eval_ids:?}");
+
+    log::info!("Crawling evals...");
+    crawl_evals::crawl_evals(
+        http_client,
+        &hydra_url,
+        data_dir,
+        store,
+        &evals,
+        Backend::Html,
+    )
+    .await
+    .in_step(Step::CrawlEvals)?;
+
+    log::info!("Calculating failing builds by platform and status...");
+    let breakdown = page::failure_breakdown(data_dir, &eval_ids).in_step(Step::CountFailures)?;
+    let (failing_builds_table, total_build_failures) = page::failing_builds_table(&breakdown);
+
+    log::info!("Calculating charts...");
+    let mut burndowns = vec![];
+    for jobset in &branch.jobsets {
+        burndowns.push(history::burndown(store, branch, jobset).in_step(Step::RenderPage)?);
+    }
+
+    log::info!("Comparing with the previous evaluations...");
+    let mut diffs = vec![];
+    for (jobset, eval) in &latest {
+        let diff = diff_evals::diff_with_previous(store, eval.id).in_step(Step::DiffEvals)?;
+        diffs.push((jobset.title.as_str(), diff));
+    }
+    diff_evals::render_changes_page(
+        &hydra_url,
+        &branch.page_url(),
+        &diffs,
+        &public_dir.join("changes.html"),
+    )
+    .in_step(Step::DiffEvals)?;
+
+    log::info!("Fetching maintainers...");
+    let maintainers_cache = data_dir.join("maintainerscache");
+    create_dir_all(&maintainers_cache)
+        .context("Failed creating the maintainers cache")
+        .in_step(Step::FetchMaintainers)?;
+    let mut to_fetch = vec![];
+    for (eval_id, jobset) in &evals {
+        let cached = maintainers_cache.join(format!("{eval_id}.cache")).exists();
+        if cached
+            && !store
+                .maintainers(*eval_id)
+                .in_step(Step::FetchMaintainers)?
+                .is_empty()
+        {
+            continue;
+        }
+        let rev = crawl_jobset::nixpkgs_revision(http_client, &hydra_url, *eval_id)
+            .await
+            .and_then(|rev| rev.ok_or_else(|| anyhow!("Evaluation {eval_id} has no nixpkgs input")))
+            .in_step(Step::FetchMaintainers)?;
+        to_fetch.push((*eval_id, rev, jobset.is_nixos()));
+    }
+    if !to_fetch.is_empty() {
+        fetch_maintainers::fetch_maintainers(data_dir, store, &to_fetch)
+            .in_step(Step::FetchMaintainers)?;
+    }
+    purge_cache(&maintainers_cache, &eval_ids).in_step(Step::FetchMaintainers)?;
+
+    log::info!("Classifying the logs of failed builds...");
+    build_logs::classify_failed_builds(http_client, &hydra_url, data_dir, store, &eval_ids)
+        .await
+        .in_step(Step::ClassifyLogs)?;
+
+    log::info!("Rendering maintainer pages...");
+    maintainer_pages::render_maintainer_pages(
+        &hydra_url,
+        &branch.page_url(),
+        data_dir,
+        store,
+        public_dir,
+        &eval_ids,
+    )
+    .in_step(Step::MaintainerPages)?;
+
+    log::info!("Rendering failure clusters...");
+    let clusters = build_logs::failure_clusters(store, &eval_ids).in_step(Step::ClassifyLogs)?;
+    build_logs::render_clusters_page(
+        &hydra_url,
+        &branch.page_url(),
+        &clusters,
+        &public_dir.join("failed").join("clusters.html"),
+    )
+    .in_step(Step::ClassifyLogs)?;
+
+    log::info!("Finding most important dependencies...");
+    most_important_deps::find_failed_dependencies(
+        http_client,
+        &hydra_url,
+        data_dir,
+        store,
+        &eval_ids,
+    )
+    .await
+    .in_step(Step::MostImportantDeps)?;
+
+    log::info!("Rendering most important builds...");
+    let most_problematic_deps =
+        page::most_problematic_deps(&hydra_url, data_dir).in_step(Step::RenderPage)?;
+
+    // Render page
+    let latest_evals: Vec<(&Jobset, &JobsetEval)> = latest
+        .iter()
+        .map(|(jobset, eval)| (*jobset, eval))
+        .collect();
+    page::render_index(
+        page_dir,
+        public_dir,
+        &[
+            ("targetbranch", branch.name.clone()),
+            ("siteurl", branch.page_url()),
+            ("evalrows", page::eval_rows(&hydra_url, &latest_evals)),
+            ("totalbuildfailures", total_build_failures.to_string()),
+            ("failingbuildstable", failing_builds_table),
+            ("burndowndatasets", page::burndown_datasets(&burndowns)),
+            ("lastcheck", last_check()),
+            ("triggered", triggered_by()),
+            ("mostproblematicdeps", most_problematic_deps),
+        ],
+    )
+    .in_step(Step::RenderPage)?;
+
+    Ok(BranchSummary {
+        branch,
+        evals: latest,
+        total_failures: total_build_failures,
+    })
+}
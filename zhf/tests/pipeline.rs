@@ -0,0 +1,343 @@
+//! Run the whole pipeline against recorded Hydra pages
+
+use hydra_fixtures::{pages_dir, FixtureServer};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::process::Command;
+use zhf_core::{Maintainer, Store, StoredEval};
+
+/// A config tracking the fixture jobsets as two branches
+const TWO_BRANCHES: &str = r#"
+[[branches]]
+name = "master"
+site_url = "https://zh.fail"
+
+[[branches.jobsets]]
+name = "linux"
+title = "Linux"
+project = "nixos"
+jobset = "trunk-combined"
+
+[[branches]]
+name = "release-23.05"
+site_url = "https://zh.fail"
+
+[[branches.jobsets]]
+name = "darwin"
+title = "Darwin"
+project = "nixpkgs"
+jobset = "trunk"
+systems = ["x86_64-darwin", "aarch64-darwin"]
+"#;
+
+/// Runs `zhf run` in `work_dir` with the maintainers of the branches already cached, as they need
+/// Nix
+fn run_pipeline(work_dir: &Path, hydra_url: &str, branches: &[&str]) {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden");
+    for branch in branches {
+        let maintainers_cache = work_dir.join("data").join(branch).join("maintainerscache");
+        std::fs::create_dir_all(&maintainers_cache).unwrap();
+        for eval in ["1001", "2000"] {
+            std::fs::copy(
+                golden
+                    .join("maintainerscache")
+                    .join(format!("{eval}.cache")),
+                maintainers_cache.join(format!("{eval}.cache")),
+            )
+            .unwrap();
+        }
+    }
+    let page_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("page");
+    if !work_dir.join("page").exists() {
+        std::os::unix::fs::symlink(page_dir, work_dir.join("page")).unwrap();
+    }
+    run_zhf(work_dir, hydra_url, &["run"]);
+}
+
+/// Runs `zhf` with the given arguments in `work_dir`
+fn run_zhf(work_dir: &Path, hydra_url: &str, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_zhf"))
+        .args(args)
+        .env("HYDRA_URL", hydra_url)
+        .env("CI_PIPELINE_SOURCE", "test")
+        .current_dir(work_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn renders_the_page() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+
+    let index = read_to_string(work_dir.path().join("public/master/index.html")).unwrap();
+    assert!(!index.contains("@evalrows@"));
+    assert!(index.contains(&format!(
+        "<tr><td>Latest Linux evaluation (completely built):</td><td><a href=\"{}/eval/1001\"><b>1001</b></a> on <b>2023-05-10 09:01:02 (UTC)</b></td></tr>",
+        server.url()
+    )));
+    assert!(index.contains(&format!(
+        "<tr><td>Latest Darwin evaluation (completely built):</td><td><a href=\"{}/eval/2000\"><b>2000</b></a> on <b>2023-05-09 23:59:59 (UTC)</b></td></tr>",
+        server.url()
+    )));
+    assert!(index.contains("<meta property=\"og:url\" content=\"https://zh.fail/master\" />"));
+    assert!(index.contains(
+        "<tr><td>Failing builds on aarch64-darwin:</td><td><b>1</b> (1 failed)</td></tr>\
+         <tr><td>Failing builds on aarch64-linux:</td><td><b>1</b> (1 timed out)</td></tr>\
+         <tr><td>Failing builds on x86_64-darwin:</td><td><b>1</b> (1 dependency failed)</td></tr>\
+         <tr><td>Failing builds on x86_64-linux:</td><td><b>3</b> (1 failed, 2 dependency failed)</td></tr>"
+    ));
+    assert!(index.contains("<tr><td>Total failed builds</td><td><b>6</b></td></tr>"));
+    assert!(index.contains(
+        "{ label: \"Linux Failures\", borderColor: '#4d6fb6', lineTension: 0, data: [{ x: '2023-05-10T09:01:02', y: '4' },] }"
+    ));
+    assert!(index.contains(
+        "{ label: \"Linux Dependency failed\", borderColor: '#4d6fb6', borderDash: [4, 4], hidden: true, lineTension: 0, data: [{ x: '2023-05-10T09:01:02', y: '2' },] }"
+    ));
+    assert!(index.contains(&format!(
+        "<tr><td><a href=\"{}/build/102\">foo-1.0</a></td><td>x86_64-linux</td>\
+         <td><details><summary>2</summary>nixos.tests.simple.x86_64-linux<br>nixpkgs.bar.x86_64-linux</details></td></tr>",
+        server.url()
+    )));
+    assert!(index.contains("(Triggered by test)"));
+    assert!(index.contains("<a href=\"changes.html\">"));
+    let changes = read_to_string(work_dir.path().join("public/master/changes.html")).unwrap();
+    assert!(changes.contains("<h2>Linux</h2><p>No earlier evaluation is recorded.</p>"));
+    assert!(work_dir
+        .path()
+        .join("public/master/failed/by-maintainer/alice.html")
+        .exists());
+
+    let bob = read_to_string(
+        work_dir
+            .path()
+            .join("public/master/failed/by-maintainer/bob.html"),
+    )
+    .unwrap();
+    assert!(bob.contains(&format!(
+        "<td>Failed</td><td data-since=\"2023-05-09T23:59:59Z\"><a href=\"{}/eval/2000\">2023-05-09 23:59:59 (UTC)</a></td>\
+         <td><details><summary>Test failure</summary><code>AssertionError: b&#39;\\x00&#39; != b&#39;\\x01&#39;</code></details></td></tr>",
+        server.url()
+    )));
+    let all = read_to_string(work_dir.path().join("public/master/failed/all.html")).unwrap();
+    assert!(all.contains(&format!(
+        "<td>alice</td><td>Failed</td><td data-since=\"2023-05-10T09:01:02Z\"><a href=\"{}/eval/1001\">2023-05-10 09:01:02 (UTC)</a></td>\
+         <td><details><summary>Compiler error</summary><code>foo.c:12:5: error: implicit declaration of function &#39;bar&#39; [-Werror=implicit-function-declaration]</code></details></td></tr>",
+        server.url()
+    )));
+    assert!(all.contains("<input type=\"number\" id=\"min-days\""));
+    let flaky = read_to_string(work_dir.path().join("public/master/failed/flaky.html")).unwrap();
+    assert!(flaky.contains(r#"<tr><td colspan="6" class="none">None 🎉</td></tr>"#));
+    let clusters =
+        read_to_string(work_dir.path().join("public/master/failed/clusters.html")).unwrap();
+    assert!(clusters.contains(&format!(
+        "<td>Test failure</td><td><code>AssertionError: b&#39;\\xN&#39; != b&#39;\\xN&#39;</code></td>\
+         <td>1</td><td>aarch64-darwin</td><td><a href=\"{}/build/202\">qux.aarch64-darwin</a></td></tr>",
+        server.url()
+    )));
+}
+
+#[test]
+fn records_history_once() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    assert_eq!(
+        store.evals("master", "linux").unwrap(),
+        vec![StoredEval {
+            id: 1001,
+            branch: "master".to_string(),
+            jobset: "linux".to_string(),
+            failures: 4,
+            time: "2023-05-10 09:01:02 (UTC)".to_string(),
+        }]
+    );
+    assert_eq!(
+        store
+            .evals("master", "darwin")
+            .unwrap()
+            .iter()
+            .map(|eval| eval.id)
+            .collect::<Vec<_>>(),
+        vec![2000]
+    );
+}
+
+#[test]
+fn records_everything_in_the_store() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    let eval = store.eval(1001).unwrap().unwrap();
+    assert_eq!(eval.builds.len(), 5);
+    assert_eq!(store.eval(2000).unwrap().unwrap().builds.len(), 3);
+    assert!(store.eval(4242).unwrap().is_none());
+    assert!(store
+        .maintainers(1001)
+        .unwrap()
+        .iter()
+        .any(|build| build.maintainer == Maintainer::Handle("alice".to_string())));
+    let blocked = store.blocked_builds(1001).unwrap();
+    assert_eq!(blocked.len(), 2);
+    assert!(blocked
+        .iter()
+        .all(|build| build.cause.name == "foo-1.0" && build.cause.build_id == 102));
+
+    // The store is shared by all branches and outlives their caches
+    drop(store);
+    std::fs::remove_dir_all(work_dir.path().join("data/master")).unwrap();
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    assert_eq!(store.eval(1001).unwrap().unwrap(), eval);
+}
+
+#[test]
+fn renders_an_overview_of_all_branches() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    std::fs::write(work_dir.path().join("zhf.toml"), TWO_BRANCHES).unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master", "release-23.05"]);
+
+    let public_dir = work_dir.path().join("public");
+    let master = read_to_string(public_dir.join("master/index.html")).unwrap();
+    assert!(master.contains("<tr><td>Total failed builds</td><td><b>4</b></td></tr>"));
+    let release = read_to_string(public_dir.join("release-23.05/index.html")).unwrap();
+    assert!(release.contains("<tr><td>Total failed builds</td><td><b>2</b></td></tr>"));
+    assert!(release.contains("<b>release-23.05</b>"));
+
+    let overview = read_to_string(public_dir.join("index.html")).unwrap();
+    assert!(overview.contains(
+        "<tr><td><a href=\"master/\">master</a></td><td><b>4</b></td>\
+         <td>Linux <b>1001</b> on 2023-05-10 09:01:02 (UTC)</td></tr>\
+         <tr><td><a href=\"release-23.05/\">release-23.05</a></td><td><b>2</b></td>\
+         <td>Darwin <b>2000</b> on 2023-05-09 23:59:59 (UTC)</td></tr>"
+    ));
+    assert!(public_dir.join("style.css").exists());
+    assert!(!public_dir.join("branches.html").exists());
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    assert_eq!(store.evals("release-23.05", "darwin").unwrap().len(), 1);
+    assert!(store.evals("master", "darwin").unwrap().is_empty());
+}
+
+#[test]
+fn imports_the_history_files_of_master() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let data_dir = work_dir.path().join("data");
+    std::fs::create_dir_all(&data_dir).unwrap();
+    std::fs::write(
+        data_dir.join("history-linux"),
+        "900 10 2023-05-01 00:00:00 (UTC)\n",
+    )
+    .unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+
+    assert!(!data_dir.join("history-linux").exists());
+    let store = Store::open_in(&data_dir).unwrap();
+    assert_eq!(
+        store
+            .evals("master", "linux")
+            .unwrap()
+            .iter()
+            .map(|eval| (eval.id, eval.failures))
+            .collect::<Vec<_>>(),
+        vec![(900, 10), (1001, 4)]
+    );
+    let index = read_to_string(work_dir.path().join("public/master/index.html")).unwrap();
+    assert!(index.contains(
+        "data: [{ x: '2023-05-01T00:00:00', y: '10' },{ x: '2023-05-10T09:01:02', y: '4' },]"
+    ));
+}
+
+#[test]
+fn runs_offline_from_the_cached_answers() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let hydra_url = server.url().to_string();
+    run_pipeline(work_dir.path(), &hydra_url, &["master"]);
+    drop(server);
+
+    run_zhf(work_dir.path(), &hydra_url, &["run", "--offline"]);
+    let index = read_to_string(work_dir.path().join("public/master/index.html")).unwrap();
+    assert!(index.contains(&format!(
+        "<a href=\"{hydra_url}/eval/1001\"><b>1001</b></a>"
+    )));
+    assert!(index.contains("<tr><td>Total failed builds</td><td><b>6</b></td></tr>"));
+}
+
+#[test]
+fn recounts_fail_caches_of_older_versions() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let fail_cache = work_dir.path().join("data/master/failcache");
+    std::fs::create_dir_all(&fail_cache).unwrap();
+    std::fs::write(
+        fail_cache.join("1001 2000.cache"),
+        "x86_64-linux 99 Failed\n",
+    )
+    .unwrap();
+    run_pipeline(work_dir.path(), server.url(), &["master"]);
+
+    let index = read_to_string(work_dir.path().join("public/master/index.html")).unwrap();
+    assert!(index.contains("<tr><td>Total failed builds</td><td><b>6</b></td></tr>"));
+    assert!(!fail_cache.join("1001 2000.cache").exists());
+}
+
+#[test]
+fn backfills_the_history() {
+    let server = FixtureServer::start(pages_dir()).unwrap();
+    let work_dir = tempfile::tempdir().unwrap();
+    let backfill = ["backfill", "master", "linux", "2023-05-08", "2023-05-11"];
+    run_zhf(work_dir.path(), server.url(), &backfill);
+    run_zhf(work_dir.path(), server.url(), &backfill);
+
+    // The unfinished eval 1002 and eval 999 before the range are skipped
+    let store = Store::open_in(&work_dir.path().join("data")).unwrap();
+    assert_eq!(
+        store
+            .evals("master", "linux")
+            .unwrap()
+            .iter()
+            .map(|eval| (eval.id, eval.failures, eval.time.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (1000, 2, "2023-05-08 18:30:00 (UTC)"),
+            (1001, 4, "2023-05-10 09:01:02 (UTC)")
+        ]
+    );
+    // Their failures are counted by status without crawling their builds
+    let breakdowns = store.failure_breakdowns("master", "linux").unwrap();
+    let statuses = |eval_id| -> Vec<(String, u64)> {
+        breakdowns[&eval_id]
+            .by_status()
+            .into_iter()
+            .map(|(status, count)| (status.to_string(), count))
+            .collect()
+    };
+    assert_eq!(
+        statuses(1000),
+        [
+            ("Output size limit exceeded".to_string(), 1),
+            ("Exploded".to_string(), 1)
+        ]
+    );
+    assert_eq!(
+        statuses(1001),
+        [
+            ("Failed".to_string(), 1),
+            ("Dependency failed".to_string(), 2),
+            ("Timed out".to_string(), 1)
+        ]
+    );
+    assert_eq!(store.eval(1001).unwrap(), None);
+}
@@ -0,0 +1,129 @@
+//! The fail cache (`data/failcache/{eval ids}.v{version}.cache`)
+
+use crate::{parse_lines, Build, BuildStatus, System};
+use anyhow::{anyhow, Context, Error, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The failed builds of a system with one status.
+///
+/// Serialized as one line of the fail cache: `system count status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FailureCount {
+    system: System,
+    status: BuildStatus,
+    count: u64,
+}
+
+impl FromStr for FailureCount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.splitn(3, ' ').collect();
+        if parts.len() != 3 {
+            return Err(anyhow!("Expected 3 fields, found {}", parts.len()));
+        }
+        Ok(FailureCount {
+            system: parts[0].parse()?,
+            count: parts[1]
+                .parse()
+                .with_context(|| format!("Invalid count {:?}", parts[1]))?,
+            status: parts[2].parse()?,
+        })
+    }
+}
+
+impl fmt::Display for FailureCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.system, self.count, self.status)
+    }
+}
+
+/// Failed builds counted by system and status
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FailureBreakdown {
+    counts: BTreeMap<(System, BuildStatus), u64>,
+}
+
+impl FailureBreakdown {
+    /// Counts the builds that didn't succeed
+    pub fn from_builds<'a>(builds: impl IntoIterator<Item = &'a Build>) -> Self {
+        let mut breakdown = FailureBreakdown::default();
+        for build in builds {
+            if build.status.is_failure() {
+                *breakdown
+                    .counts
+                    .entry((build.system.clone(), build.status.clone()))
+                    .or_insert(0) += 1;
+            }
+        }
+        breakdown
+    }
+
+    /// Adds failed builds of a system with a status
+    pub(crate) fn add(&mut self, system: System, status: BuildStatus, count: u64) {
+        *self.counts.entry((system, status)).or_insert(0) += count;
+    }
+
+    /// The number of failed builds of each system and status
+    pub fn iter(&self) -> impl Iterator<Item = (&System, &BuildStatus, u64)> {
+        self.counts
+            .iter()
+            .map(|((system, status), count)| (system, status, *count))
+    }
+
+    /// The number of failed builds of each system
+    pub fn by_system(&self) -> BTreeMap<&System, u64> {
+        let mut systems = BTreeMap::new();
+        for (system, _, count) in self.iter() {
+            *systems.entry(system).or_insert(0) += count;
+        }
+        systems
+    }
+
+    /// The number of failed builds with each status
+    pub fn by_status(&self) -> BTreeMap<&BuildStatus, u64> {
+        let mut statuses = BTreeMap::new();
+        for (_, status, count) in self.iter() {
+            *statuses.entry(status).or_insert(0) += count;
+        }
+        statuses
+    }
+
+    /// The number of failed builds of a system with each status
+    pub fn statuses_of(&self, system: &System) -> BTreeMap<&BuildStatus, u64> {
+        self.iter()
+            .filter(|(s, _, _)| *s == system)
+            .map(|(_, status, count)| (status, count))
+            .collect()
+    }
+
+    /// The number of failed builds
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Parses the contents of a fail cache file
+    pub fn parse_cache(contents: &str) -> Result<Self> {
+        let counts = parse_lines::<FailureCount>(contents)?
+            .into_iter()
+            .map(|c| ((c.system, c.status), c.count))
+            .collect();
+        Ok(FailureBreakdown { counts })
+    }
+
+    /// Renders the contents of a fail cache file
+    pub fn to_cache(&self) -> String {
+        self.iter()
+            .map(|(system, status, count)| {
+                let line = FailureCount {
+                    system: system.clone(),
+                    status: status.clone(),
+                    count,
+                };
+                format!("{line}\n")
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,272 @@
+//! A single Hydra build of an evaluation
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The result of a Hydra build as shown in the title of its status icon
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BuildStatus {
+    Succeeded,
+    Failed,
+    DependencyFailed,
+    Aborted,
+    Cancelled,
+    /// The build failed, but produced outputs anyway
+    FailedWithOutput,
+    TimedOut,
+    /// The build failed before and wasn't tried again
+    CachedFailure,
+    UnsupportedSystem,
+    LogLimitExceeded,
+    OutputLimitExceeded,
+    NonDeterministic,
+    /// Waiting in the queue
+    Scheduled,
+    /// Being built right now
+    InProgress,
+    /// A status this version doesn't know, kept verbatim
+    Unknown(String),
+}
+
+impl BuildStatus {
+    /// Whether this build failed because one of its dependencies did
+    pub fn is_dependency_failure(&self) -> bool {
+        *self == BuildStatus::DependencyFailed
+    }
+
+    /// Whether this build finished without succeeding. Unknown statuses count as failures, so
+    /// they show up somewhere.
+    pub fn is_failure(&self) -> bool {
+        self.is_finished() && *self != BuildStatus::Succeeded
+    }
+
+    /// Whether this build failed by itself rather than because one of its dependencies did
+    pub fn is_own_failure(&self) -> bool {
+        self.is_failure() && !self.is_dependency_failure()
+    }
+
+    /// Whether a build going from this status to `other` flipped between succeeding and failing
+    /// by itself, which is what flaky builds do
+    pub fn flips_to(&self, other: &BuildStatus) -> bool {
+        (*self == BuildStatus::Succeeded && other.is_own_failure())
+            || (self.is_own_failure() && *other == BuildStatus::Succeeded)
+    }
+
+    /// Whether this build is no longer queued or running
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, BuildStatus::Scheduled | BuildStatus::InProgress)
+    }
+
+    /// Whether this status was recognized
+    pub fn is_known(&self) -> bool {
+        !matches!(self, BuildStatus::Unknown(_))
+    }
+}
+
+impl FromStr for BuildStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Err(anyhow!("Empty build status"));
+        }
+        Ok(match s {
+            "Succeeded" => BuildStatus::Succeeded,
+            "Failed" => BuildStatus::Failed,
+            "Dependency failed" => BuildStatus::DependencyFailed,
+            "Aborted" => BuildStatus::Aborted,
+            "Cancelled" => BuildStatus::Cancelled,
+            "Failed with output" => BuildStatus::FailedWithOutput,
+            "Timed out" => BuildStatus::TimedOut,
+            "Cached failure" => BuildStatus::CachedFailure,
+            "Unsupported system type" => BuildStatus::UnsupportedSystem,
+            "Log limit exceeded" => BuildStatus::LogLimitExceeded,
+            // Older Hydra versions leave out "size"
+            "Output size limit exceeded" | "Output limit exceeded" => {
+                BuildStatus::OutputLimitExceeded
+            }
+            "Non-deterministic build" => BuildStatus::NonDeterministic,
+            "Scheduled to be built" => BuildStatus::Scheduled,
+            "Build in progress" => BuildStatus::InProgress,
+            other => BuildStatus::Unknown(other.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for BuildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BuildStatus::Succeeded => "Succeeded",
+            BuildStatus::Failed => "Failed",
+            BuildStatus::DependencyFailed => "Dependency failed",
+            BuildStatus::Aborted => "Aborted",
+            BuildStatus::Cancelled => "Cancelled",
+            BuildStatus::FailedWithOutput => "Failed with output",
+            BuildStatus::TimedOut => "Timed out",
+            BuildStatus::CachedFailure => "Cached failure",
+            BuildStatus::UnsupportedSystem => "Unsupported system type",
+            BuildStatus::LogLimitExceeded => "Log limit exceeded",
+            BuildStatus::OutputLimitExceeded => "Output size limit exceeded",
+            BuildStatus::NonDeterministic => "Non-deterministic build",
+            BuildStatus::Scheduled => "Scheduled to be built",
+            BuildStatus::InProgress => "Build in progress",
+            BuildStatus::Unknown(other) => other,
+        })
+    }
+}
+
+/// A Nix system double like `x86_64-linux`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct System(String);
+
+impl System {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_darwin(&self) -> bool {
+        self.0.ends_with("-darwin")
+    }
+}
+
+impl FromStr for System {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(anyhow!("Invalid system {s:?}"));
+        }
+        Ok(System(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for System {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for System {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The directory all store paths are in
+pub const STORE_DIR: &str = "/nix/store/";
+
+/// The name of a store path, without the directory and the hash, e.g. `foo-1.0-dev` for
+/// `/nix/store/{hash}-foo-1.0-dev`
+pub fn store_path_name(path: &str) -> Option<&str> {
+    let base = path.strip_prefix(STORE_DIR)?;
+    // Hashes are 32 characters of Nix base32
+    match base.as_bytes().get(32) {
+        Some(b'-') if base.len() > 33 => Some(&base[33..]),
+        _ => None,
+    }
+}
+
+/// One job of an evaluation.
+///
+/// Serialized as one line of the eval cache:
+/// `attr build_id name system status [drv_path [output=path...]]`. The store paths come last, as
+/// statuses contain spaces, and are left out if they're not known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    /// The attribute path of the job, including the system
+    pub attr: String,
+    pub id: u64,
+    /// The name of the job (usually `pname-version`)
+    pub name: String,
+    pub system: System,
+    pub status: BuildStatus,
+    /// The store path of the derivation, if it was crawled
+    pub drv_path: Option<String>,
+    /// The store paths of the outputs by output name, empty if they weren't crawled
+    pub outputs: BTreeMap<String, String>,
+}
+
+impl Build {
+    /// The outputs as stored, `output=path` separated by spaces
+    pub(crate) fn outputs_string(&self) -> String {
+        self.outputs
+            .iter()
+            .map(|(name, path)| format!("{name}={path}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Parses an output given as `output=path`
+fn parse_output(s: &str) -> Option<(String, String)> {
+    let (name, path) = s.split_once('=')?;
+    (!name.is_empty() && path.starts_with(STORE_DIR)).then(|| (name.to_string(), path.to_string()))
+}
+
+/// Parses the outputs as stored, `output=path` separated by spaces
+pub(crate) fn parse_outputs(s: &str) -> Result<BTreeMap<String, String>> {
+    s.split_whitespace()
+        .map(|output| parse_output(output).ok_or_else(|| anyhow!("Invalid output {output:?}")))
+        .collect()
+}
+
+impl FromStr for Build {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.splitn(5, ' ').collect();
+        if parts.len() != 5 {
+            return Err(anyhow!("Expected 5 fields, found {}", parts.len()));
+        }
+        // Split the store paths off the end of the status
+        let mut status = parts[4];
+        let mut drv_path = None;
+        let mut outputs = BTreeMap::new();
+        while let Some((rest, last)) = status.rsplit_once(' ') {
+            if let Some((name, path)) = parse_output(last) {
+                outputs.insert(name, path);
+            } else if last.starts_with(STORE_DIR) {
+                drv_path = Some(last.to_string());
+                status = rest;
+                break;
+            } else {
+                break;
+            }
+            status = rest;
+        }
+        Ok(Build {
+            attr: parts[0].to_string(),
+            id: parts[1]
+                .parse()
+                .with_context(|| format!("Invalid build ID {:?}", parts[1]))?,
+            name: parts[2].to_string(),
+            system: parts[3].parse()?,
+            status: status.parse()?,
+            drv_path,
+            outputs,
+        })
+    }
+}
+
+impl fmt::Display for Build {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.attr, self.id, self.name, self.system, self.status
+        )?;
+        if let Some(drv_path) = &self.drv_path {
+            write!(f, " {drv_path}")?;
+        }
+        if !self.outputs.is_empty() {
+            write!(f, " {}", self.outputs_string())?;
+        }
+        Ok(())
+    }
+}
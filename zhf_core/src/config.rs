@@ -0,0 +1,216 @@
+//! The configuration file (`zhf.toml`)
+
+use crate::System;
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The configuration used when there is no configuration file
+const DEFAULT_CONFIG: &str = include_str!("../../zhf.toml");
+
+/// The Hydra instance to crawl when a branch doesn't configure one
+pub const DEFAULT_HYDRA_URL: &str = "https://hydra.nixos.org";
+
+fn default_hydra_url() -> String {
+    DEFAULT_HYDRA_URL.to_string()
+}
+
+/// All tracked branches
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub http: HttpConfig,
+    pub branches: Vec<Branch>,
+}
+
+/// Limits of the requests to Hydra, shared by all requests of a process
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct HttpConfig {
+    /// How many requests may be in flight at once
+    pub max_in_flight: usize,
+    /// How many requests are started per second at most
+    pub requests_per_second: f64,
+    /// How often a request that failed transiently is retried
+    pub max_retries: u32,
+    /// How large the response cache may get, in bytes
+    pub max_cache_size: u64,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            max_in_flight: 8,
+            requests_per_second: 10.0,
+            max_retries: 10,
+            max_cache_size: 1_000_000_000,
+        }
+    }
+}
+
+/// A tracked nixpkgs branch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Branch {
+    pub name: String,
+    #[serde(default = "default_hydra_url")]
+    hydra_url: String,
+    /// Where the rendered site is published
+    site_url: String,
+    pub jobsets: Vec<Jobset>,
+}
+
+/// A Hydra jobset building a branch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Jobset {
+    /// Short name used for files, e.g. `linux`
+    pub name: String,
+    /// Name shown on the page, e.g. `Linux`
+    pub title: String,
+    pub project: String,
+    pub jobset: String,
+    /// Only builds for these systems are taken from the jobset, all if unset
+    pub systems: Option<Vec<System>>,
+}
+
+/// Whether `name` can be used as a single path component
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(|c: char| c == '/' || c == '\\' || c.is_whitespace())
+}
+
+impl Config {
+    /// Loads the file given by `ZHF_CONFIG`, falling back to `zhf.toml` in the current directory
+    /// and then to the built-in configuration
+    pub fn load() -> Result<Self> {
+        if let Some(path) = std::env::var_os("ZHF_CONFIG") {
+            return Config::read(Path::new(&path));
+        }
+        let path = Path::new("zhf.toml");
+        if path.exists() {
+            return Config::read(path);
+        }
+        Config::parse(DEFAULT_CONFIG)
+    }
+
+    /// Reads the configuration file at `path`
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed reading {}", path.display()))?;
+        Config::parse(&contents).with_context(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Parses and validates a configuration
+    pub fn parse(contents: &str) -> Result<Self> {
+        let config: Config = toml::from_str(contents)?;
+        if config.http.max_in_flight == 0 {
+            return Err(anyhow!("http.max_in_flight must be at least 1"));
+        }
+        let rps = config.http.requests_per_second;
+        if !rps.is_finite() || rps <= 0.0 {
+            return Err(anyhow!("http.requests_per_second must be positive"));
+        }
+        if config.branches.is_empty() {
+            return Err(anyhow!("No branches configured"));
+        }
+        let mut branch_names = HashSet::new();
+        for branch in &config.branches {
+            if !is_file_name(&branch.name) {
+                return Err(anyhow!(
+                    "Branch name {:?} can't be used as a directory",
+                    branch.name
+                ));
+            }
+            if !branch_names.insert(&branch.name) {
+                return Err(anyhow!("Branch {} is configured twice", branch.name));
+            }
+            if branch.jobsets.is_empty() {
+                return Err(anyhow!("Branch {} has no jobsets", branch.name));
+            }
+            let mut jobset_names = HashSet::new();
+            for jobset in &branch.jobsets {
+                if !is_file_name(&jobset.name) {
+                    return Err(anyhow!(
+                        "Jobset name {:?} can't be used in a file name",
+                        jobset.name
+                    ));
+                }
+                if !jobset_names.insert(&jobset.name) {
+                    return Err(anyhow!(
+                        "Jobset {} of branch {} is configured twice",
+                        jobset.name,
+                        branch.name
+                    ));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Finds a branch by name. Without a name, the branch named by `ZHF_BRANCH` or the first one
+    /// is used.
+    pub fn branch(&self, name: Option<&str>) -> Result<&Branch> {
+        let name = name
+            .map(str::to_string)
+            .or_else(|| std::env::var("ZHF_BRANCH").ok());
+        match name {
+            Some(name) => self
+                .branches
+                .iter()
+                .find(|branch| branch.name == name)
+                .ok_or_else(|| anyhow!("Branch {name} is not configured")),
+            None => Ok(&self.branches[0]),
+        }
+    }
+}
+
+impl Branch {
+    /// The base URL of the Hydra instance to crawl, without a trailing slash.
+    ///
+    /// Can be overridden with the `HYDRA_URL` environment variable, e.g. to point the crawlers at
+    /// a local fixture server.
+    pub fn hydra_url(&self) -> String {
+        std::env::var("HYDRA_URL")
+            .unwrap_or_else(|_| self.hydra_url.clone())
+            .trim_end_matches('/')
+            .to_string()
+    }
+
+    /// The URL the site is published at, without a trailing slash
+    pub fn site_url(&self) -> &str {
+        self.site_url.trim_end_matches('/')
+    }
+
+    /// The URL the page of this branch is published at, without a trailing slash
+    pub fn page_url(&self) -> String {
+        format!("{}/{}", self.site_url(), self.name)
+    }
+
+    /// Finds a jobset by name
+    pub fn jobset(&self, name: &str) -> Result<&Jobset> {
+        self.jobsets
+            .iter()
+            .find(|jobset| jobset.name == name)
+            .ok_or_else(|| anyhow!("Jobset {name} is not configured for branch {}", self.name))
+    }
+}
+
+impl Jobset {
+    /// Whether builds for `system` are taken from this jobset
+    pub fn takes(&self, system: &System) -> bool {
+        self.systems
+            .as_ref()
+            .map(|systems| systems.contains(system))
+            .unwrap_or(true)
+    }
+
+    /// Whether jobs are named like in `nixos:trunk-combined` (`nixpkgs.hello.x86_64-linux`)
+    pub fn is_nixos(&self) -> bool {
+        self.project == "nixos"
+    }
+}
@@ -0,0 +1,89 @@
+//! Deduplication of the builds of several evaluations
+//!
+//! The jobsets of a branch evaluate many of the same derivations under different attributes, like
+//! `nixpkgs.hello.x86_64-linux` in `nixos:trunk-combined` and `hello.x86_64-linux` in
+//! `nixpkgs:trunk`. Builds are the same if they have the same derivation or if Hydra reused the same
+//! build for them. Builds whose derivation isn't known are the same as the other builds of their
+//! attribute, ignoring the `nixpkgs.` prefix of `nixos:trunk-combined`. If the attribute was built
+//! from several derivations, they are only merged into the first one.
+
+use crate::{Build, Eval};
+use std::collections::{BTreeSet, HashMap};
+
+/// A build that is referenced by one or more evaluations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueBuild {
+    /// The newest of the builds of the derivation
+    pub build: Build,
+    /// Every evaluation and attribute referencing the derivation
+    pub references: BTreeSet<(u64, String)>,
+}
+
+/// The attribute of a build without the `nixpkgs.` prefix of `nixos:trunk-combined`
+fn package_attr(attr: &str) -> &str {
+    attr.strip_prefix("nixpkgs.").unwrap_or(attr)
+}
+
+/// Finds the parent of a group, flattening the path to it
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Deduplicates the builds of the given evaluations, sorted by the attribute and ID of the build
+/// that is kept
+pub fn dedup_builds<'a>(evals: impl IntoIterator<Item = &'a Eval>) -> Vec<UniqueBuild> {
+    let builds: Vec<(u64, &Build)> = evals
+        .into_iter()
+        .flat_map(|eval| eval.builds.iter().map(move |build| (eval.id, build)))
+        .collect();
+
+    // Join the builds sharing any of their keys
+    let mut parents: Vec<usize> = (0..builds.len()).collect();
+    let mut by_id: HashMap<u64, usize> = HashMap::new();
+    let mut by_drv: HashMap<&str, usize> = HashMap::new();
+    // The first build of each attribute with a derivation, and without one
+    let mut by_attr_with_drv: HashMap<&str, usize> = HashMap::new();
+    let mut by_attr: HashMap<&str, usize> = HashMap::new();
+    for (i, (_, build)) in builds.iter().enumerate() {
+        let attr = package_attr(&build.attr);
+        let mut same = vec![*by_id.entry(build.id).or_insert(i)];
+        match &build.drv_path {
+            Some(drv_path) => {
+                same.push(*by_drv.entry(drv_path).or_insert(i));
+                if !by_attr_with_drv.contains_key(attr) {
+                    by_attr_with_drv.insert(attr, i);
+                    same.extend(by_attr.get(attr));
+                }
+            }
+            None => {
+                same.push(*by_attr.entry(attr).or_insert(i));
+                same.extend(by_attr_with_drv.get(attr));
+            }
+        }
+        for other in same {
+            let (root, other) = (find(&mut parents, i), find(&mut parents, other));
+            parents[other] = root;
+        }
+    }
+
+    let mut groups: HashMap<usize, UniqueBuild> = HashMap::new();
+    for (i, (eval_id, build)) in builds.iter().enumerate() {
+        let group = groups
+            .entry(find(&mut parents, i))
+            .or_insert_with(|| UniqueBuild {
+                build: (*build).clone(),
+                references: BTreeSet::new(),
+            });
+        if build.id > group.build.id {
+            group.build = (*build).clone();
+        }
+        group.references.insert((*eval_id, build.attr.clone()));
+    }
+    let mut unique: Vec<UniqueBuild> = groups.into_values().collect();
+    unique.sort_by(|a, b| (&a.build.attr, a.build.id).cmp(&(&b.build.attr, b.build.id)));
+    unique
+}
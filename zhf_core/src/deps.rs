@@ -0,0 +1,77 @@
+//! The most important dependencies cache (`data/mostimportantcache/{id}.cache`)
+
+use crate::System;
+use anyhow::{anyhow, Context, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// A failed build step that caused a build to fail with "Dependency failed".
+///
+/// Serialized as `name;system;build_id`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FailedDependency {
+    /// The store path name of the failed derivation, without the hash
+    pub name: String,
+    pub system: System,
+    /// The build that actually failed
+    pub build_id: u64,
+}
+
+impl FromStr for FailedDependency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(';').collect();
+        if parts.len() != 3 {
+            return Err(anyhow!("Expected 3 fields, found {}", parts.len()));
+        }
+        Ok(FailedDependency {
+            name: parts[0].to_string(),
+            system: parts[1].parse()?,
+            build_id: parts[2]
+                .parse()
+                .with_context(|| format!("Invalid build ID {:?}", parts[2]))?,
+        })
+    }
+}
+
+impl fmt::Display for FailedDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};{};{}", self.name, self.system, self.build_id)
+    }
+}
+
+/// A build that failed with "Dependency failed", together with the root cause of the failure,
+/// which is the step that originally failed after following all propagated failures.
+///
+/// Serialized as `name;system;build_id;attr`, one line per build and root cause.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BlockedBuild {
+    /// The attribute of the blocked build
+    pub attr: String,
+    pub cause: FailedDependency,
+}
+
+impl FromStr for BlockedBuild {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(';').collect();
+        if parts.len() != 4 {
+            return Err(anyhow!("Expected 4 fields, found {}", parts.len()));
+        }
+        if parts[3].is_empty() {
+            return Err(anyhow!("Empty attribute"));
+        }
+        Ok(BlockedBuild {
+            attr: parts[3].to_string(),
+            cause: parts[..3].join(";").parse()?,
+        })
+    }
+}
+
+impl fmt::Display for BlockedBuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};{}", self.cause, self.attr)
+    }
+}
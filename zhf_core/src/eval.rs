@@ -0,0 +1,52 @@
+//! The eval cache (`data/evalcache/{id}.cache`)
+
+use crate::{parse_lines, Build};
+use anyhow::{Context, Result};
+use std::fs::{read_to_string, File};
+use std::io::Write as _;
+use std::path::Path;
+
+/// All builds of a Hydra evaluation, sorted by attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eval {
+    pub id: u64,
+    pub builds: Vec<Build>,
+}
+
+impl Eval {
+    /// Creates a new evaluation, sorting the builds by attribute
+    pub fn new(id: u64, mut builds: Vec<Build>) -> Self {
+        builds.sort_by(|a, b| a.attr.cmp(&b.attr));
+        Eval { id, builds }
+    }
+
+    /// Parses the contents of an eval cache file
+    pub fn parse_cache(id: u64, contents: &str) -> Result<Self> {
+        let builds =
+            parse_lines(contents).with_context(|| format!("Invalid cache of eval {id}"))?;
+        Ok(Eval::new(id, builds))
+    }
+
+    /// Renders the contents of an eval cache file
+    pub fn to_cache(&self) -> String {
+        self.builds
+            .iter()
+            .map(|build| format!("{build}\n"))
+            .collect()
+    }
+
+    /// Reads the eval cache file at `path`
+    pub fn read_cache(id: u64, path: &Path) -> Result<Self> {
+        let contents =
+            read_to_string(path).with_context(|| format!("Failed reading {}", path.display()))?;
+        Eval::parse_cache(id, &contents)
+    }
+
+    /// Writes the eval cache file to `path`
+    pub fn write_cache(&self, path: &Path) -> Result<()> {
+        let mut out =
+            File::create(path).with_context(|| format!("Failed creating {}", path.display()))?;
+        out.write_all(self.to_cache().as_bytes())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+//! Shared data model for the ZHF tools.
+//!
+//! All binaries exchange data through plain-text cache files below `data/`. This crate contains
+//! the types these files describe together with the parsers and serializers for every format, so
+//! no binary needs to slice lines up by hand. Everything written to the caches is also recorded in
+//! the history store, which keeps it after the caches are purged.
+
+mod breakdown;
+mod build;
+mod config;
+mod dedup;
+mod deps;
+mod eval;
+mod logs;
+mod maintainers;
+mod store;
+
+pub use breakdown::FailureBreakdown;
+pub use build::{store_path_name, Build, BuildStatus, System, STORE_DIR};
+pub use config::{Branch, Config, HttpConfig, Jobset, DEFAULT_HYDRA_URL};
+pub use dedup::{dedup_builds, UniqueBuild};
+pub use deps::{BlockedBuild, FailedDependency};
+pub use eval::Eval;
+pub use logs::{ClassifiedLog, FailureCategory};
+pub use maintainers::{MaintainedBuild, Maintainer};
+pub use store::{Store, StoredEval, STORE_FILE};
+
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// Parses every non-empty line of a cache file, reporting the offending line number on errors
+pub fn parse_lines<T>(contents: &str) -> Result<Vec<T>>
+where
+    T: FromStr<Err = anyhow::Error>,
+{
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            line.parse()
+                .with_context(|| format!("Malformed line {}: {line:?}", i + 1))
+        })
+        .collect()
+}
+
+/// Escapes text for HTML, as text from Hydra like log lines is full of `<` and `&`
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,104 @@
+//! The log cache (`data/logcache/{id}.cache`)
+
+use anyhow::{anyhow, Context, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// What went wrong in a failed build, judging by the tail of its log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureCategory {
+    TestFailure,
+    HashMismatch,
+    CompilerError,
+    MissingDependency,
+    Timeout,
+    OutOfMemory,
+    /// The build tried to reach the network or something else outside of the sandbox
+    Sandbox,
+    /// No rule matched
+    Unknown,
+}
+
+impl FailureCategory {
+    /// The name shown on the pages
+    pub fn title(self) -> &'static str {
+        match self {
+            FailureCategory::TestFailure => "Test failure",
+            FailureCategory::HashMismatch => "Hash mismatch",
+            FailureCategory::CompilerError => "Compiler error",
+            FailureCategory::MissingDependency => "Missing dependency",
+            FailureCategory::Timeout => "Timeout",
+            FailureCategory::OutOfMemory => "Out of memory",
+            FailureCategory::Sandbox => "Sandbox or network access",
+            FailureCategory::Unknown => "Unknown",
+        }
+    }
+}
+
+impl FromStr for FailureCategory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "test-failure" => FailureCategory::TestFailure,
+            "hash-mismatch" => FailureCategory::HashMismatch,
+            "compiler-error" => FailureCategory::CompilerError,
+            "missing-dependency" => FailureCategory::MissingDependency,
+            "timeout" => FailureCategory::Timeout,
+            "out-of-memory" => FailureCategory::OutOfMemory,
+            "sandbox" => FailureCategory::Sandbox,
+            "unknown" => FailureCategory::Unknown,
+            other => return Err(anyhow!("Unknown failure category {other:?}")),
+        })
+    }
+}
+
+impl fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureCategory::TestFailure => "test-failure",
+            FailureCategory::HashMismatch => "hash-mismatch",
+            FailureCategory::CompilerError => "compiler-error",
+            FailureCategory::MissingDependency => "missing-dependency",
+            FailureCategory::Timeout => "timeout",
+            FailureCategory::OutOfMemory => "out-of-memory",
+            FailureCategory::Sandbox => "sandbox",
+            FailureCategory::Unknown => "unknown",
+        })
+    }
+}
+
+/// The classified log of a build that failed by itself.
+///
+/// Serialized as `build_id category line`, where `line` is the line of the log that gave the
+/// category away and may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassifiedLog {
+    pub build_id: u64,
+    pub category: FailureCategory,
+    pub line: String,
+}
+
+impl FromStr for ClassifiedLog {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.splitn(3, ' ').collect();
+        if parts.len() < 2 {
+            return Err(anyhow!("Expected 3 fields, found {}", parts.len()));
+        }
+        Ok(ClassifiedLog {
+            build_id: parts[0]
+                .parse()
+                .with_context(|| format!("Invalid build ID {:?}", parts[0]))?,
+            category: parts[1].parse()?,
+            line: parts.get(2).copied().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for ClassifiedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.build_id, self.category, self.line)
+    }
+}
@@ -0,0 +1,70 @@
+//! The maintainers cache (`data/maintainerscache/{id}.cache`)
+
+use crate::Build;
+use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Who maintains a build
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Maintainer {
+    /// The GitHub handle of a maintainer
+    Handle(String),
+    /// The build has no maintainer with a GitHub handle
+    Nobody,
+    /// The maintainers of the build couldn't be evaluated
+    Unknown,
+}
+
+impl FromStr for Maintainer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" => Err(anyhow!("Empty maintainer")),
+            "_" => Ok(Maintainer::Nobody),
+            "!" => Ok(Maintainer::Unknown),
+            handle => Ok(Maintainer::Handle(handle.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Maintainer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Maintainer::Handle(handle) => f.write_str(handle),
+            Maintainer::Nobody => f.write_str("_"),
+            Maintainer::Unknown => f.write_str("!"),
+        }
+    }
+}
+
+/// A build together with one of its maintainers.
+///
+/// Serialized as `maintainer attr build_id name system status`, with `_` standing in for builds
+/// without any maintainer and `!` for builds whose maintainers couldn't be evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaintainedBuild {
+    pub maintainer: Maintainer,
+    pub build: Build,
+}
+
+impl FromStr for MaintainedBuild {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (maintainer, build) = s
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("No maintainer found"))?;
+        Ok(MaintainedBuild {
+            maintainer: maintainer.parse()?,
+            build: build.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for MaintainedBuild {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.maintainer, self.build)
+    }
+}
@@ -0,0 +1,595 @@
+//! The history store (`data/zhf.sqlite`)
+//!
+//! Unlike the cache files, nothing is ever purged from the store, so every evaluation that was
+//! ever processed can still be queried.
+
+use crate::build::parse_outputs;
+use crate::{
+    BlockedBuild, Build, ClassifiedLog, Eval, FailedDependency, FailureBreakdown, MaintainedBuild,
+    Maintainer,
+};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// The file name of the store below `data/`
+pub const STORE_FILE: &str = "zhf.sqlite";
+
+/// Schema migrations, `PRAGMA user_version` is the number of migrations that were applied
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE evals (
+        id INTEGER PRIMARY KEY,
+        branch TEXT NOT NULL,
+        jobset TEXT NOT NULL,
+        -- As reported by Hydra, not deduplicated
+        failures INTEGER NOT NULL,
+        -- As shown by Hydra, e.g. `2023-05-10 09:01:02 (UTC)`
+        time TEXT NOT NULL
+    );
+    CREATE INDEX evals_by_jobset ON evals (branch, jobset);
+    CREATE TABLE builds (
+        eval_id INTEGER NOT NULL,
+        attr TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        system TEXT NOT NULL,
+        status TEXT NOT NULL,
+        PRIMARY KEY (eval_id, attr)
+    );
+    CREATE TABLE maintainers (
+        eval_id INTEGER NOT NULL,
+        -- NULL for builds without any maintainer
+        maintainer TEXT,
+        attr TEXT NOT NULL,
+        build_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        system TEXT NOT NULL,
+        status TEXT NOT NULL
+    );
+    CREATE INDEX maintainers_by_eval ON maintainers (eval_id);
+    CREATE TABLE failed_deps (
+        eval_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        system TEXT NOT NULL,
+        build_id INTEGER NOT NULL
+    );
+    CREATE INDEX failed_deps_by_eval ON failed_deps (eval_id);
+"#,
+    r#"
+    CREATE INDEX builds_by_attr ON builds (attr, eval_id);
+"#,
+    r#"
+    -- The blocked build, NULL for rows recorded before root causes were followed
+    ALTER TABLE failed_deps ADD COLUMN attr TEXT;
+"#,
+    r#"
+    -- The classified logs of builds that failed by themselves, a log never changes
+    CREATE TABLE build_logs (
+        build_id INTEGER PRIMARY KEY,
+        category TEXT NOT NULL,
+        -- The line that gave the category away, may be empty
+        line TEXT NOT NULL
+    );
+"#,
+    r#"
+    -- The derivation of the build, NULL if it wasn't crawled
+    ALTER TABLE builds ADD COLUMN drv_path TEXT;
+"#,
+    r#"
+    -- The outputs of the build as `output=path` separated by spaces, NULL if they weren't crawled
+    ALTER TABLE builds ADD COLUMN outputs TEXT;
+"#,
+    r#"
+    -- Hydra reuses builds across evaluations, so their store paths are looked up by ID
+    CREATE INDEX builds_by_id ON builds (id);
+"#,
+    r#"
+    -- The failed builds of an evaluation by system and status, for the burndown chart. Kept apart
+    -- from the builds, since backfilled evaluations only have their counts.
+    CREATE TABLE failure_counts (
+        eval_id INTEGER NOT NULL,
+        system TEXT NOT NULL,
+        status TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        PRIMARY KEY (eval_id, system, status)
+    );
+    INSERT INTO failure_counts (eval_id, system, status, count)
+        SELECT eval_id, system, status, COUNT(*) FROM builds
+        WHERE status NOT IN ('Succeeded', 'Scheduled to be built', 'Build in progress')
+        GROUP BY eval_id, system, status;
+"#,
+];
+
+/// The `outputs` column of a build, NULL if its outputs weren't crawled
+fn outputs_column(build: &Build) -> Option<String> {
+    (!build.outputs.is_empty()).then(|| build.outputs_string())
+}
+
+/// Replaces the failure counts of an evaluation
+fn write_failure_breakdown(
+    conn: &Connection,
+    eval_id: u64,
+    breakdown: &FailureBreakdown,
+) -> Result<()> {
+    conn.execute("DELETE FROM failure_counts WHERE eval_id = ?", [eval_id])?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO failure_counts (eval_id, system, status, count) VALUES (?, ?, ?, ?)",
+    )?;
+    for (system, status, count) in breakdown.iter() {
+        stmt.execute(params![eval_id, system.as_str(), status.to_string(), count])?;
+    }
+    Ok(())
+}
+
+/// A finished evaluation of a jobset of a branch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredEval {
+    pub id: u64,
+    pub branch: String,
+    /// The name of the jobset in the config
+    pub jobset: String,
+    pub failures: u64,
+    pub time: String,
+}
+
+/// Reads a row of `SELECT id, branch, jobset, failures, time FROM evals`
+fn stored_eval_from_row(row: &Row) -> rusqlite::Result<StoredEval> {
+    stored_eval_from_row_at(row, 0)
+}
+
+/// Reads the columns `id, branch, jobset, failures, time` of an eval starting at column `i`
+fn stored_eval_from_row_at(row: &Row, i: usize) -> rusqlite::Result<StoredEval> {
+    Ok(StoredEval {
+        id: row.get(i)?,
+        branch: row.get(i + 1)?,
+        jobset: row.get(i + 2)?,
+        failures: row.get(i + 3)?,
+        time: row.get(i + 4)?,
+    })
+}
+
+impl StoredEval {
+    /// The time of the evaluation in ISO 8601, e.g. `2023-05-10T09:01:02Z`
+    pub fn iso_time(&self) -> String {
+        format!(
+            "{}Z",
+            self.time.trim_end_matches(" (UTC)").replacen(' ', "T", 1)
+        )
+    }
+}
+
+/// The SQLite database all evaluations, builds, maintainers and failed dependencies are recorded in
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens the store in `data_dir`, creating both if needed
+    pub fn open_in(data_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(data_dir)?;
+        Store::open(&data_dir.join(STORE_FILE))
+    }
+
+    /// Opens the store at `path`, creating it and migrating its schema if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed opening the store {}", path.display()))?;
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute_batch(migration)
+                .with_context(|| format!("Failed migrating the store to version {}", i + 1))?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        Ok(Store { conn })
+    }
+
+    /// Records an evaluation unless it's already recorded
+    pub fn record_eval(&self, eval: &StoredEval) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO evals (id, branch, jobset, failures, time) VALUES (?, ?, ?, ?, ?)",
+            params![eval.id, eval.branch, eval.jobset, eval.failures, eval.time],
+        )?;
+        Ok(())
+    }
+
+    /// All recorded evaluations of a jobset, sorted by ID
+    pub fn evals(&self, branch: &str, jobset: &str) -> Result<Vec<StoredEval>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, branch, jobset, failures, time FROM evals
+             WHERE branch = ? AND jobset = ? ORDER BY id",
+        )?;
+        let evals = stmt
+            .query_map(params![branch, jobset], stored_eval_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(evals)
+    }
+
+    /// Looks up a recorded evaluation
+    pub fn stored_eval(&self, eval_id: u64) -> Result<Option<StoredEval>> {
+        let eval = self
+            .conn
+            .query_row(
+                "SELECT id, branch, jobset, failures, time FROM evals WHERE id = ?",
+                [eval_id],
+                stored_eval_from_row,
+            )
+            .optional()?;
+        Ok(eval)
+    }
+
+    /// The latest evaluation of the same jobset before `eval` that has its builds recorded
+    pub fn previous_eval(&self, eval: &StoredEval) -> Result<Option<StoredEval>> {
+        let eval = self
+            .conn
+            .query_row(
+                "SELECT id, branch, jobset, failures, time FROM evals
+                 WHERE branch = ? AND jobset = ? AND id < ?
+                   AND EXISTS (SELECT 1 FROM builds WHERE eval_id = evals.id)
+                 ORDER BY id DESC LIMIT 1",
+                params![eval.branch, eval.jobset, eval.id],
+                stored_eval_from_row,
+            )
+            .optional()?;
+        Ok(eval)
+    }
+
+    /// The first evaluation of each attribute since which it failed in every evaluation of the
+    /// jobset up to `eval`. Attributes that succeeded in `eval` are left out. Failures that started
+    /// before the first evaluation with recorded builds are reported as failing since that one.
+    pub fn failing_since(&self, eval: &StoredEval) -> Result<HashMap<String, StoredEval>> {
+        let mut stmt = self.conn.prepare(
+            // The last success of each attribute is found in one pass, the first failure after it
+            // with the index on attributes
+            "SELECT f.attr, e.id, e.branch, e.jobset, e.failures, e.time FROM (
+                 SELECT l.attr, (
+                     SELECT MIN(b.eval_id) FROM builds b
+                     WHERE b.attr = l.attr AND b.eval_id > l.succeeded AND b.eval_id <= ?3
+                       AND b.eval_id IN (SELECT id FROM evals WHERE branch = ?1 AND jobset = ?2)
+                 ) AS since FROM (
+                     SELECT b.attr,
+                         MAX(CASE WHEN b.status = 'Succeeded' THEN b.eval_id ELSE 0 END) AS succeeded
+                     FROM builds b JOIN evals e ON e.id = b.eval_id
+                     WHERE e.branch = ?1 AND e.jobset = ?2 AND b.eval_id <= ?3
+                     GROUP BY b.attr
+                 ) l
+             ) f JOIN evals e ON e.id = f.since",
+        )?;
+        let since = stmt
+            .query_map(params![eval.branch, eval.jobset, eval.id], |row| {
+                Ok((row.get(0)?, stored_eval_from_row_at(row, 1)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(since)
+    }
+
+    /// Records all builds of an evaluation and their failure counts, replacing the ones recorded
+    /// before
+    pub fn record_builds(&self, eval: &Eval) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM builds WHERE eval_id = ?", [eval.id])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO builds (eval_id, attr, id, name, system, status, drv_path, outputs)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for build in &eval.builds {
+                stmt.execute(params![
+                    eval.id,
+                    build.attr,
+                    build.id,
+                    build.name,
+                    build.system.as_str(),
+                    build.status.to_string(),
+                    build.drv_path,
+                    outputs_column(build),
+                ])?;
+            }
+        }
+        write_failure_breakdown(&tx, eval.id, &FailureBreakdown::from_builds(&eval.builds))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the failure counts of an evaluation whose builds aren't recorded, replacing the ones
+    /// recorded before
+    pub fn record_failure_breakdown(
+        &self,
+        eval_id: u64,
+        breakdown: &FailureBreakdown,
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        write_failure_breakdown(&tx, eval_id, breakdown)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The recorded failure counts of the evaluations of a jobset, by evaluation ID. Evaluations
+    /// without any failures only have counts if their builds are recorded.
+    pub fn failure_breakdowns(
+        &self,
+        branch: &str,
+        jobset: &str,
+    ) -> Result<BTreeMap<u64, FailureBreakdown>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT e.id, c.system, c.status, c.count FROM evals e
+             LEFT JOIN failure_counts c ON c.eval_id = e.id
+             WHERE e.branch = ? AND e.jobset = ?
+               AND (c.eval_id IS NOT NULL OR EXISTS (SELECT 1 FROM builds WHERE eval_id = e.id))",
+        )?;
+        let rows = stmt
+            .query_map(params![branch, jobset], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<u64>>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut breakdowns: BTreeMap<u64, FailureBreakdown> = BTreeMap::new();
+        for (eval_id, system, status, count) in rows {
+            let breakdown = breakdowns.entry(eval_id).or_default();
+            if let (Some(system), Some(status), Some(count)) = (system, status, count) {
+                let context = || format!("Invalid failure counts of eval {eval_id} in the store");
+                breakdown.add(
+                    system.parse().with_context(context)?,
+                    status.parse().with_context(context)?,
+                    count,
+                );
+            }
+        }
+        Ok(breakdowns)
+    }
+
+    /// Records the store paths of builds that were crawled later, in every evaluation they belong
+    /// to
+    pub fn record_store_paths(&self, builds: &[Build]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt =
+                tx.prepare("UPDATE builds SET drv_path = ?, outputs = ? WHERE id = ?")?;
+            for build in builds {
+                stmt.execute(params![build.drv_path, outputs_column(build), build.id])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Fills in the store paths of builds that don't know them yet from the builds with the same ID
+    /// recorded for any evaluation
+    pub fn fill_store_paths(&self, builds: &mut [Build]) -> Result<()> {
+        let mut stmt = self.conn.prepare(
+            "SELECT drv_path, outputs FROM builds
+             WHERE id = ? AND drv_path IS NOT NULL LIMIT 1",
+        )?;
+        for build in builds.iter_mut().filter(|build| build.drv_path.is_none()) {
+            let paths = stmt
+                .query_row([build.id], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+                })
+                .optional()?;
+            if let Some((drv_path, outputs)) = paths {
+                build.outputs = parse_outputs(outputs.as_deref().unwrap_or_default())
+                    .with_context(|| {
+                        format!("Invalid outputs of build {} in the store", build.id)
+                    })?;
+                build.drv_path = Some(drv_path);
+            }
+        }
+        Ok(())
+    }
+
+    /// The recorded builds of an evaluation, if there are any
+    pub fn eval(&self, eval_id: u64) -> Result<Option<Eval>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT attr, id, name, system, status, drv_path, outputs FROM builds
+             WHERE eval_id = ? ORDER BY attr",
+        )?;
+        let rows = stmt
+            .query_map([eval_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let builds = rows
+            .into_iter()
+            .map(|(attr, id, name, system, status, drv_path, outputs)| {
+                Ok(Build {
+                    attr,
+                    id,
+                    name,
+                    system: system.parse()?,
+                    status: status.parse()?,
+                    drv_path,
+                    outputs: parse_outputs(outputs.as_deref().unwrap_or_default())?,
+                })
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid builds of eval {eval_id} in the store"))?;
+        Ok(Some(Eval::new(eval_id, builds)))
+    }
+
+    /// Records the maintainers of the failed builds of an evaluation, replacing the ones recorded
+    /// before. Maintainers that couldn't be evaluated are recorded as `!`.
+    pub fn record_maintainers(&self, eval_id: u64, builds: &[MaintainedBuild]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM maintainers WHERE eval_id = ?", [eval_id])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO maintainers (eval_id, maintainer, attr, build_id, name, system, status)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            for MaintainedBuild { maintainer, build } in builds {
+                let maintainer = match maintainer {
+                    Maintainer::Nobody => None,
+                    maintainer => Some(maintainer.to_string()),
+                };
+                stmt.execute(params![
+                    eval_id,
+                    maintainer,
+                    build.attr,
+                    build.id,
+                    build.name,
+                    build.system.as_str(),
+                    build.status.to_string(),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the builds of an evaluation that failed with "Dependency failed" together with
+    /// their root causes, replacing the ones recorded before
+    pub fn record_blocked_builds(&self, eval_id: u64, builds: &[BlockedBuild]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM failed_deps WHERE eval_id = ?", [eval_id])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO failed_deps (eval_id, name, system, build_id, attr)
+                 VALUES (?, ?, ?, ?, ?)",
+            )?;
+            for build in builds {
+                stmt.execute(params![
+                    eval_id,
+                    build.cause.name,
+                    build.cause.system.as_str(),
+                    build.cause.build_id,
+                    build.attr
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records classified build logs, replacing the ones recorded for the same builds
+    pub fn record_classified_logs(&self, logs: &[ClassifiedLog]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO build_logs (build_id, category, line) VALUES (?, ?, ?)",
+            )?;
+            for log in logs {
+                stmt.execute(params![log.build_id, log.category.to_string(), log.line])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The recorded classified logs of the builds of an evaluation, by build ID
+    pub fn classified_logs(&self, eval_id: u64) -> Result<HashMap<u64, ClassifiedLog>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT l.build_id, l.category, l.line FROM build_logs l
+             JOIN builds b ON b.id = l.build_id WHERE b.eval_id = ?",
+        )?;
+        let rows = stmt
+            .query_map([eval_id], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(build_id, category, line)| {
+                let log = ClassifiedLog {
+                    build_id,
+                    category: category.parse()?,
+                    line,
+                };
+                Ok((build_id, log))
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid build logs of eval {eval_id} in the store"))
+    }
+
+    /// The recorded maintainers of the failed builds of an evaluation
+    pub fn maintainers(&self, eval_id: u64) -> Result<Vec<MaintainedBuild>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT maintainer, attr, build_id, name, system, status FROM maintainers
+             WHERE eval_id = ? ORDER BY rowid",
+        )?;
+        let rows = stmt
+            .query_map([eval_id], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(maintainer, attr, id, name, system, status)| {
+                Ok(MaintainedBuild {
+                    maintainer: match maintainer {
+                        Some(maintainer) => maintainer.parse()?,
+                        None => Maintainer::Nobody,
+                    },
+                    build: Build {
+                        attr,
+                        id,
+                        name,
+                        system: system.parse()?,
+                        status: status.parse()?,
+                        drv_path: None,
+                        outputs: BTreeMap::new(),
+                    },
+                })
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid maintainers of eval {eval_id} in the store"))
+    }
+
+    /// The recorded blocked builds of an evaluation with their root causes. Failed dependencies
+    /// recorded before root causes were followed are left out.
+    pub fn blocked_builds(&self, eval_id: u64) -> Result<Vec<BlockedBuild>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, system, build_id, attr FROM failed_deps
+             WHERE eval_id = ? AND attr IS NOT NULL ORDER BY rowid",
+        )?;
+        let rows = stmt
+            .query_map([eval_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.into_iter()
+            .map(|(name, system, build_id, attr)| {
+                Ok(BlockedBuild {
+                    attr,
+                    cause: FailedDependency {
+                        name,
+                        system: system.parse()?,
+                        build_id,
+                    },
+                })
+            })
+            .collect::<Result<_>>()
+            .with_context(|| format!("Invalid blocked builds of eval {eval_id} in the store"))
+    }
+}
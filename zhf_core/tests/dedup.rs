@@ -0,0 +1,105 @@
+//! Deduplicate the builds of several jobsets
+
+use zhf_core::{dedup_builds, Eval};
+
+const DRV: &str = "/nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv";
+
+#[test]
+fn dedups_builds_by_derivation() {
+    let nixos = Eval::parse_cache(
+        1001,
+        &format!(
+            "nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed {DRV}\n\
+             nixpkgs.hello.x86_64-linux 101 hello-2.12.1 x86_64-linux Succeeded\n"
+        ),
+    )
+    .unwrap();
+    let nixpkgs = Eval::parse_cache(
+        2000,
+        &format!(
+            "foo.x86_64-linux 302 foo-1.0 x86_64-linux Failed {DRV}\n\
+             hello.x86_64-linux 101 hello-2.12.1 x86_64-linux Succeeded\n\
+             qux.aarch64-darwin 202 qux-1.0 aarch64-darwin Failed\n"
+        ),
+    )
+    .unwrap();
+
+    let unique = dedup_builds([&nixos, &nixpkgs]);
+    let summary: Vec<(u64, Vec<(u64, &str)>)> = unique
+        .iter()
+        .map(|unique| {
+            let references = unique
+                .references
+                .iter()
+                .map(|(eval, attr)| (*eval, attr.as_str()))
+                .collect();
+            (unique.build.id, references)
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            // The same derivation, built twice
+            (
+                302,
+                vec![
+                    (1001, "nixpkgs.foo.x86_64-linux"),
+                    (2000, "foo.x86_64-linux")
+                ]
+            ),
+            // The same build, reused by Hydra
+            (
+                101,
+                vec![
+                    (1001, "nixpkgs.hello.x86_64-linux"),
+                    (2000, "hello.x86_64-linux")
+                ]
+            ),
+            (202, vec![(2000, "qux.aarch64-darwin")]),
+        ]
+    );
+}
+
+#[test]
+fn keeps_different_derivations_of_an_attr_apart() {
+    let old = Eval::parse_cache(
+        1000,
+        &format!(
+            "foo.x86_64-linux 94 foo-1.0 x86_64-linux Failed {DRV}\n\
+             bar.x86_64-linux 93 bar-2.0 x86_64-linux Dependency failed\n"
+        ),
+    )
+    .unwrap();
+    let new = Eval::parse_cache(
+        1001,
+        "foo.x86_64-linux 102 foo-1.1 x86_64-linux Failed \
+         /nix/store/zzsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.1.drv\n\
+         bar.x86_64-linux 103 bar-2.0 x86_64-linux Dependency failed\n",
+    )
+    .unwrap();
+
+    let unique = dedup_builds([&old, &new]);
+    let ids: Vec<u64> = unique.iter().map(|unique| unique.build.id).collect();
+    // Without a derivation, builds are only told apart by their attribute
+    assert_eq!(ids, [103, 94, 102]);
+}
+
+#[test]
+fn merges_builds_without_derivation_into_their_attr() {
+    // The lookup of the derivation failed in one of the jobsets
+    let nixos = Eval::parse_cache(
+        1001,
+        "nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed\n",
+    )
+    .unwrap();
+    let nixpkgs = Eval::parse_cache(
+        2000,
+        &format!("foo.x86_64-linux 302 foo-1.0 x86_64-linux Failed {DRV}\n"),
+    )
+    .unwrap();
+
+    let unique = dedup_builds([&nixos, &nixpkgs]);
+    assert_eq!(unique.len(), 1);
+    assert_eq!(unique[0].build.id, 302);
+    assert_eq!(unique[0].references.len(), 2);
+}
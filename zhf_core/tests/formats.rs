@@ -0,0 +1,133 @@
+//! Parse and render the lines of the cache files
+
+use std::collections::BTreeMap;
+use zhf_core::{
+    parse_lines, BlockedBuild, Build, BuildStatus, FailedDependency, MaintainedBuild, Maintainer,
+};
+
+/// Asserts that a line parses and renders back to itself
+fn assert_round_trip<T>(line: &str) -> T
+where
+    T: std::str::FromStr<Err = anyhow::Error> + std::fmt::Display,
+{
+    let parsed: T = line.parse().unwrap();
+    assert_eq!(parsed.to_string(), line);
+    parsed
+}
+
+/// The error of a line that doesn't parse, with its causes
+fn error_of<T>(line: &str) -> String
+where
+    T: std::str::FromStr<Err = anyhow::Error> + std::fmt::Debug,
+{
+    format!("{:#}", line.parse::<T>().unwrap_err())
+}
+
+#[test]
+fn builds() {
+    let build: Build =
+        assert_round_trip("nixpkgs.bar.x86_64-linux 103 bar-2.0 x86_64-linux Dependency failed");
+    assert_eq!(build.id, 103);
+    assert_eq!(build.status, BuildStatus::DependencyFailed);
+    assert_eq!(build.drv_path, None);
+    assert!(build.outputs.is_empty());
+
+    let build: Build = assert_round_trip(
+        "nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed \
+         /nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv \
+         dev=/nix/store/2h1f7a1ph4n0c7fn6r9xfh0cxrjvg8w7-foo-1.0-dev \
+         out=/nix/store/rv2pl28z8nyq8q3bpdcpqqxjijfb4w4c-foo-1.0",
+    );
+    assert_eq!(build.status, BuildStatus::Failed);
+    assert_eq!(
+        build.drv_path.as_deref(),
+        Some("/nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv")
+    );
+    assert_eq!(
+        build.outputs,
+        BTreeMap::from([
+            (
+                "dev".to_string(),
+                "/nix/store/2h1f7a1ph4n0c7fn6r9xfh0cxrjvg8w7-foo-1.0-dev".to_string()
+            ),
+            (
+                "out".to_string(),
+                "/nix/store/rv2pl28z8nyq8q3bpdcpqqxjijfb4w4c-foo-1.0".to_string()
+            ),
+        ])
+    );
+
+    assert_eq!(
+        error_of::<Build>("nixpkgs.foo.x86_64-linux 102 foo-1.0"),
+        "Expected 5 fields, found 3"
+    );
+    assert_eq!(
+        error_of::<Build>("nixpkgs.foo.x86_64-linux abc foo-1.0 x86_64-linux Failed"),
+        "Invalid build ID \"abc\": invalid digit found in string"
+    );
+    assert_eq!(
+        error_of::<Build>("nixpkgs.foo.x86_64-linux 102 foo-1.0  Failed"),
+        "Invalid system \"\""
+    );
+}
+
+#[test]
+fn failed_dependencies() {
+    let dep: FailedDependency = assert_round_trip("foo-1.0;x86_64-linux;102");
+    assert_eq!(dep.name, "foo-1.0");
+    assert_eq!(dep.build_id, 102);
+    let blocked: BlockedBuild =
+        assert_round_trip("foo-1.0;x86_64-linux;102;nixpkgs.bar.x86_64-linux");
+    assert_eq!(blocked.cause, dep);
+
+    assert_eq!(
+        error_of::<FailedDependency>("foo-1.0;x86_64-linux"),
+        "Expected 3 fields, found 2"
+    );
+    assert_eq!(
+        error_of::<FailedDependency>("foo-1.0;x86_64-linux;x"),
+        "Invalid build ID \"x\": invalid digit found in string"
+    );
+    assert_eq!(
+        error_of::<BlockedBuild>("foo-1.0;x86_64-linux;102;"),
+        "Empty attribute"
+    );
+}
+
+#[test]
+fn maintained_builds() {
+    let line = "alice nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed";
+    let build: MaintainedBuild = assert_round_trip(line);
+    assert_eq!(build.maintainer, Maintainer::Handle("alice".to_string()));
+    let build: MaintainedBuild =
+        assert_round_trip("_ nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed");
+    assert_eq!(build.maintainer, Maintainer::Nobody);
+    let build: MaintainedBuild =
+        assert_round_trip("! nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed");
+    assert_eq!(build.maintainer, Maintainer::Unknown);
+
+    assert_eq!(error_of::<MaintainedBuild>("alice"), "No maintainer found");
+    assert_eq!(
+        error_of::<MaintainedBuild>(" nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed"),
+        "Empty maintainer"
+    );
+    assert_eq!(
+        error_of::<MaintainedBuild>("alice nixpkgs.foo.x86_64-linux 102"),
+        "Expected 5 fields, found 2"
+    );
+}
+
+#[test]
+fn reports_the_line_of_malformed_lines() {
+    let builds: Vec<FailedDependency> =
+        parse_lines("foo-1.0;x86_64-linux;102\n\nbar-2.0;aarch64-linux;103\n").unwrap();
+    assert_eq!(builds.len(), 2);
+
+    let error =
+        parse_lines::<FailedDependency>("foo-1.0;x86_64-linux;102\nbar-2.0;aarch64-linux\n")
+            .unwrap_err();
+    assert_eq!(
+        format!("{error:#}"),
+        "Malformed line 2: \"bar-2.0;aarch64-linux\": Expected 3 fields, found 2"
+    );
+}
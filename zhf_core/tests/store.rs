@@ -0,0 +1,238 @@
+//! Record evaluations in the store and query their history
+
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use zhf_core::{Eval, FailureBreakdown, Maintainer, Store, StoredEval};
+
+fn stored_eval(id: u64, jobset: &str) -> StoredEval {
+    StoredEval {
+        id,
+        branch: "unstable".to_string(),
+        jobset: jobset.to_string(),
+        failures: 0,
+        time: format!("2023-05-{:02} 09:00:00 (UTC)", id % 100),
+    }
+}
+
+/// Records an evaluation of a jobset with builds given as cache lines
+fn record(store: &Store, id: u64, jobset: &str, builds: &str) -> StoredEval {
+    let eval = stored_eval(id, jobset);
+    store.record_eval(&eval).unwrap();
+    if !builds.is_empty() {
+        store
+            .record_builds(&Eval::parse_cache(id, builds).unwrap())
+            .unwrap();
+    }
+    eval
+}
+
+#[test]
+fn migrates_a_version_1_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("zhf.sqlite");
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE evals (
+             id INTEGER PRIMARY KEY, branch TEXT NOT NULL, jobset TEXT NOT NULL,
+             failures INTEGER NOT NULL, time TEXT NOT NULL
+         );
+         CREATE INDEX evals_by_jobset ON evals (branch, jobset);
+         CREATE TABLE builds (
+             eval_id INTEGER NOT NULL, attr TEXT NOT NULL, id INTEGER NOT NULL, name TEXT NOT NULL,
+             system TEXT NOT NULL, status TEXT NOT NULL, PRIMARY KEY (eval_id, attr)
+         );
+         CREATE TABLE maintainers (
+             eval_id INTEGER NOT NULL, maintainer TEXT, attr TEXT NOT NULL,
+             build_id INTEGER NOT NULL, name TEXT NOT NULL, system TEXT NOT NULL,
+             status TEXT NOT NULL
+         );
+         CREATE INDEX maintainers_by_eval ON maintainers (eval_id);
+         CREATE TABLE failed_deps (
+             eval_id INTEGER NOT NULL, name TEXT NOT NULL, system TEXT NOT NULL,
+             build_id INTEGER NOT NULL
+         );
+         CREATE INDEX failed_deps_by_eval ON failed_deps (eval_id);
+         INSERT INTO evals VALUES (1000, 'unstable', 'nixos', 2, '2023-05-08 18:30:00 (UTC)');
+         INSERT INTO builds VALUES
+             (1000, 'nixpkgs.foo.x86_64-linux', 102, 'foo-1.0', 'x86_64-linux', 'Failed');
+         INSERT INTO maintainers VALUES
+             (1000, NULL, 'nixpkgs.foo.x86_64-linux', 102, 'foo-1.0', 'x86_64-linux', 'Failed');
+         INSERT INTO failed_deps VALUES (1000, 'bar-2.0', 'x86_64-linux', 202);
+         PRAGMA user_version = 1;",
+    )
+    .unwrap();
+    drop(conn);
+
+    let store = Store::open(&path).unwrap();
+    let eval = store.eval(1000).unwrap().unwrap();
+    assert_eq!(eval.builds.len(), 1);
+    assert_eq!(eval.builds[0].id, 102);
+    assert_eq!(eval.builds[0].drv_path, None);
+    assert_eq!(eval.builds[0].outputs, BTreeMap::new());
+    let maintainers = store.maintainers(1000).unwrap();
+    assert_eq!(maintainers[0].maintainer, Maintainer::Nobody);
+    // Failed dependencies without the blocked attribute are left out
+    assert!(store.blocked_builds(1000).unwrap().is_empty());
+    assert!(store.classified_logs(1000).unwrap().is_empty());
+    // The failures of recorded builds are counted
+    let breakdowns = store.failure_breakdowns("unstable", "nixos").unwrap();
+    assert_eq!(breakdowns[&1000].total(), 1);
+
+    // The new columns can be written
+    let drv = "/nix/store/9gsmbc9mlbxfybdqkj7xyai7nbhs0dv7-foo-1.0.drv";
+    let out = "/nix/store/3ya4zj8ms6bml4yjfh3ysh7f7n6w9bnb-foo-1.0";
+    let crawled = Eval::parse_cache(
+        1000,
+        &format!("nixpkgs.foo.x86_64-linux 102 foo-1.0 x86_64-linux Failed {drv} out={out}\n"),
+    )
+    .unwrap();
+    store.record_store_paths(&crawled.builds).unwrap();
+    drop(store);
+
+    // Reopening doesn't migrate again
+    let store = Store::open(&path).unwrap();
+    assert_eq!(store.eval(1000).unwrap().unwrap(), crawled);
+    let conn = Connection::open(&path).unwrap();
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(version, 8);
+}
+
+#[test]
+fn counts_failures_by_eval() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(dir.path()).unwrap();
+    record(
+        &store,
+        1000,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n\
+         bar 103 bar-1.0 x86_64-linux Dependency failed\n\
+         baz 104 baz-1.0 aarch64-linux Failed\n\
+         hello 101 hello-2.12.1 x86_64-linux Succeeded\n",
+    );
+    record(
+        &store,
+        1001,
+        "nixos",
+        "hello 101 hello-2.12.1 x86_64-linux Succeeded\n",
+    );
+    // Backfilled, without builds
+    record(&store, 1002, "nixos", "");
+    let breakdown = FailureBreakdown::from_builds(
+        &Eval::parse_cache(1002, "foo 202 foo-1.0 x86_64-linux Timed out\n")
+            .unwrap()
+            .builds,
+    );
+    store.record_failure_breakdown(1002, &breakdown).unwrap();
+    // Neither builds nor counts
+    record(&store, 1003, "nixos", "");
+
+    let breakdowns = store.failure_breakdowns("unstable", "nixos").unwrap();
+    assert_eq!(
+        breakdowns.keys().copied().collect::<Vec<_>>(),
+        [1000, 1001, 1002]
+    );
+    let counts: Vec<(&str, String, u64)> = breakdowns[&1000]
+        .iter()
+        .map(|(system, status, count)| (system.as_str(), status.to_string(), count))
+        .collect();
+    assert_eq!(
+        counts,
+        [
+            ("aarch64-linux", "Failed".to_string(), 1),
+            ("x86_64-linux", "Failed".to_string(), 1),
+            ("x86_64-linux", "Dependency failed".to_string(), 1)
+        ]
+    );
+    assert_eq!(breakdowns[&1001].total(), 0);
+    assert_eq!(breakdowns[&1002], breakdown);
+}
+
+#[test]
+fn finds_the_previous_eval_with_builds() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(dir.path()).unwrap();
+    let first = record(
+        &store,
+        1000,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n",
+    );
+    // Without builds
+    record(&store, 1001, "nixos", "");
+    // Of another jobset
+    record(
+        &store,
+        1002,
+        "nixpkgs",
+        "foo 102 foo-1.0 x86_64-linux Failed\n",
+    );
+    let latest = record(
+        &store,
+        1003,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n",
+    );
+
+    assert_eq!(store.previous_eval(&latest).unwrap(), Some(first.clone()));
+    assert_eq!(store.previous_eval(&first).unwrap(), None);
+}
+
+#[test]
+fn finds_since_when_attrs_are_failing() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open_in(dir.path()).unwrap();
+    // Failures of another jobset don't count
+    record(
+        &store,
+        900,
+        "nixpkgs",
+        "foo 1 foo-1.0 x86_64-linux Failed\n",
+    );
+    let first = record(
+        &store,
+        1000,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n\
+         bar 103 bar-1.0 x86_64-linux Succeeded\n\
+         qux 104 qux-1.0 x86_64-linux Failed\n\
+         hello 101 hello-2.12.1 x86_64-linux Succeeded\n",
+    );
+    let second = record(
+        &store,
+        1001,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n\
+         bar 203 bar-1.1 x86_64-linux Failed\n\
+         qux 204 qux-1.1 x86_64-linux Succeeded\n\
+         hello 101 hello-2.12.1 x86_64-linux Succeeded\n",
+    );
+    let latest = record(
+        &store,
+        1002,
+        "nixos",
+        "foo 102 foo-1.0 x86_64-linux Failed\n\
+         bar 203 bar-1.1 x86_64-linux Failed\n\
+         qux 304 qux-1.2 x86_64-linux Failed\n\
+         baz 305 baz-1.0 x86_64-linux Failed\n\
+         hello 101 hello-2.12.1 x86_64-linux Succeeded\n",
+    );
+
+    let since = store.failing_since(&latest).unwrap();
+    let since: BTreeMap<&str, u64> = since
+        .iter()
+        .map(|(attr, eval)| (attr.as_str(), eval.id))
+        .collect();
+    assert_eq!(
+        since,
+        BTreeMap::from([("bar", 1001), ("baz", 1002), ("foo", 1000), ("qux", 1002)])
+    );
+
+    // Only evaluations up to the given one are looked at
+    let since = store.failing_since(&second).unwrap();
+    assert_eq!(since["foo"], first);
+    assert_eq!(since["bar"], second);
+    assert!(!since.contains_key("qux"));
+}